@@ -23,18 +23,14 @@ pub async fn make_client(subs: ds::Subscriptions) -> Client {
         tracing::error!(error = ?err, "encountered an error");
     }));
 
-    let mut user = wheel.user();
-
     let discord = ds::Discord::new(ds::DiscordApp::PlainId(APP_ID), subs, Box::new(handler))
         .expect("unable to create discord client");
 
     tracing::info!("waiting for handshake...");
-    user.0.changed().await.unwrap();
-
-    let user = match &*user.0.borrow() {
-        ds::wheel::UserState::Connected(user) => user.clone(),
-        ds::wheel::UserState::Disconnected(err) => panic!("failed to connect to Discord: {}", err),
-    };
+    let user = discord
+        .wait_ready(std::time::Duration::from_secs(30))
+        .await
+        .expect("failed to connect to Discord");
 
     tracing::info!("connected to Discord, local user is {:#?}", user);
 
@@ -10,6 +10,8 @@ async fn main() -> Result<(), anyhow::Error> {
     let relationships = client.discord.get_relationships().await?;
     tracing::info!("got relationships: {:#?}", relationships);
 
+    client.wheel.seed_relationships(relationships.clone());
+
     let relationships = std::sync::Arc::new(relations::state::Relationships::new(relationships));
     let rs = relationships.clone();
 
@@ -17,7 +19,21 @@ async fn main() -> Result<(), anyhow::Error> {
         while let Ok(re) = rel_events.recv().await {
             tracing::info!(event = ?re, "received relationship event");
 
-            rs.on_event(re);
+            for diff in rs.on_event(re) {
+                tracing::info!(diff = ?diff, "relationship changed");
+            }
+        }
+    });
+
+    let mut presence_events = client.wheel.presence().0;
+
+    tokio::task::spawn(async move {
+        while let Ok(pe) = presence_events.recv().await {
+            tracing::info!(
+                user = %pe.relationship.user.username,
+                transition = ?pe.transition,
+                "friend presence changed"
+            );
         }
     });
 
@@ -28,7 +44,7 @@ async fn main() -> Result<(), anyhow::Error> {
     .await
     .expect("failed to spawn task");
 
-    tracing::info!("current relationship states: {:#?}", relationships);
+    tracing::info!("current relationship states: {:#?}", relationships.all());
 
     client.discord.disconnect().await;
 
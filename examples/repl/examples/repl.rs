@@ -33,7 +33,13 @@ enum ActivityCmd {
         /// The unique identifier for the user
         id: String,
     },
-    Accept,
+    /// Lists the pending invites
+    Invites,
+    /// Accepts the most recent invite, or the invite from the specified user
+    Accept {
+        /// The unique identifier for the user that sent the invite
+        id: Option<String>,
+    },
     Reply {
         #[clap(long)]
         accept: bool,
@@ -98,26 +104,24 @@ async fn main() -> Result<(), anyhow::Error> {
     let wheel = client.wheel;
     let discord = client.discord;
 
-    let (invites_tx, invites_rx) = ds::cc::unbounded();
+    let invites = std::sync::Arc::new(activity::invites::InviteInbox::new(
+        std::time::Duration::from_secs(10 * 60),
+    ));
     let (joins_tx, joins_rx) = ds::cc::unbounded();
 
     let mut activity_events = wheel.activity().0;
+    let inbox = invites.clone();
     tokio::task::spawn(async move {
         use activity::events::ActivityEvent;
         while let Ok(ae) = activity_events.recv().await {
             match ae {
-                ActivityEvent::Invite(invite) => {
-                    if invites_tx.send(invite).is_err() {
-                        break;
-                    }
-                }
                 ActivityEvent::JoinRequest(jre) => {
                     tracing::info!("Received join request from {}", jre.user);
                     if joins_tx.send(jre.user.id).is_err() {
                         break;
                     }
                 }
-                _ => {}
+                ae => inbox.on_event(ae),
             }
         }
     });
@@ -128,21 +132,25 @@ async fn main() -> Result<(), anyhow::Error> {
 
     let relationships = std::sync::Arc::new(relations::state::Relationships::new(relationships));
     let rs = relationships.clone();
+    let inbox = invites.clone();
     tokio::task::spawn(async move {
         while let Ok(re) = rl_events.recv().await {
             tracing::info!(event = ?re, "relationship event");
-            rs.on_event(re);
+            inbox.on_relationship_event(&re);
+            for diff in rs.on_event(re) {
+                tracing::info!(diff = ?diff, "relationship changed");
+            }
         }
     });
 
     struct ReplState {
-        invites_rx: ds::cc::Receiver<activity::events::InviteEvent>,
+        invites: std::sync::Arc<activity::invites::InviteInbox>,
         joins_rx: ds::cc::Receiver<ds::user::UserId>,
         relationships: std::sync::Arc<relations::state::Relationships>,
     }
 
     let mut repl_state = ReplState {
-        invites_rx,
+        invites,
         joins_rx,
         relationships,
     };
@@ -177,11 +185,34 @@ async fn main() -> Result<(), anyhow::Error> {
                     match &cmd.cmd {
                         Commands::Exit => unreachable!(),
                         Commands::Activity { cmd: activity } => match activity {
-                            ActivityCmd::Accept => {
-                                let invite =
-                                    state.invites_rx.try_recv().context("no pending invites")?;
+                            ActivityCmd::Invites => {
+                                for invite in state.invites.list() {
+                                    tracing::info!(
+                                        "{} ({}) invited you to {:?}",
+                                        invite.0.user,
+                                        invite.0.user.id,
+                                        invite.0.kind,
+                                    );
+                                }
+                            }
+                            ActivityCmd::Accept { id } => {
+                                let invites = state.invites.list();
+                                let invite = match id {
+                                    Some(id) => {
+                                        let user_id: ds::user::UserId =
+                                            id.parse().context("invalid user id")?;
+                                        invites
+                                            .into_iter()
+                                            .find(|invite| invite.0.user.id == user_id)
+                                    }
+                                    None => invites.into_iter().next(),
+                                }
+                                .context("no pending invites")?;
 
-                                discord.accept_invite(&invite).await?;
+                                state
+                                    .invites
+                                    .accept(discord, &invite.as_ref().into())
+                                    .await?;
                             }
                             ActivityCmd::Reply { accept } => {
                                 let user = state
@@ -252,10 +283,11 @@ async fn main() -> Result<(), anyhow::Error> {
                                     .await?;
                             }
                             OverlayCmd::Voice => {
-                                tracing::warn!(
-                                    "Not sending the overlay voice settings RPC as Discord will crash"
-                                );
-                                //client.open_voice_settings().await?;
+                                discord
+                                    .open_voice_settings_with(
+                                        overlay::OverlayFallback::FocusDiscord,
+                                    )
+                                    .await?;
                             }
                             OverlayCmd::GuildInvite { code } => {
                                 discord.open_guild_invite(code).await?;
@@ -263,7 +295,7 @@ async fn main() -> Result<(), anyhow::Error> {
                         },
                         Commands::Relations { cmd: rc } => match rc {
                             RelationsCmd::Print => {
-                                tracing::info!("{:#?}", state.relationships.relationships.read());
+                                tracing::info!("{:#?}", state.relationships.all());
                             }
                         },
                     }
@@ -2,6 +2,11 @@
 //! , also known as Rich Presence
 
 pub mod events;
+pub mod invites;
+pub mod join;
+pub mod schedule;
+pub mod session;
+pub mod template;
 
 use crate::{user::UserId, Command, CommandKind, Error};
 use serde::{Deserialize, Serialize};
@@ -10,7 +15,7 @@ use serde::{Deserialize, Serialize};
 /// provide much on top of this
 ///
 /// [API docs](https://discord.com/developers/docs/game-sdk/activities#data-models-activityparty-struct)
-#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Party {
     /// A unique identifier for this party
     pub id: String,
@@ -60,7 +65,7 @@ impl IntoTimestamp for i64 {
 /// developer settings.
 ///
 /// [Tips](https://discord.com/developers/docs/rich-presence/best-practices#have-interesting-expressive-art)
-#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Assets {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub large_image: Option<String>,
@@ -124,11 +129,56 @@ pub struct Timestamps {
     pub end: Option<i64>,
 }
 
+bitflags::bitflags! {
+    /// Flags describing what an activity supports
+    ///
+    /// [API docs](https://discord.com/developers/docs/topics/gateway-events#activity-object-activity-flags)
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+    pub struct ActivityFlags: u32 {
+        const INSTANCE = 1 << 0;
+        const JOIN = 1 << 1;
+        const SPECTATE = 1 << 2;
+        const JOIN_REQUEST = 1 << 3;
+        const SYNC = 1 << 4;
+        const PLAY = 1 << 5;
+        const PARTY_PRIVACY_FRIENDS = 1 << 6;
+        const PARTY_PRIVACY_VOICE_CHANNEL = 1 << 7;
+        const EMBEDDED = 1 << 8;
+    }
+}
+
+impl<'de> Deserialize<'de> for ActivityFlags {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        // Retain unknown bits rather than failing if Discord adds new flags
+        u32::deserialize(deserializer).map(Self::from_bits_retain)
+    }
+}
+
+impl Serialize for ActivityFlags {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_u32(self.bits())
+    }
+}
+
 #[derive(
-    serde_repr::Serialize_repr, serde_repr::Deserialize_repr, PartialEq, Eq, Debug, Copy, Clone,
+    serde_repr::Serialize_repr,
+    serde_repr::Deserialize_repr,
+    PartialEq,
+    Eq,
+    Debug,
+    Copy,
+    Clone,
+    Default,
 )]
 #[repr(u8)]
 pub enum ActivityKind {
+    #[default]
     Playing = 0,
     Streaming = 1,
     Listening = 2,
@@ -137,12 +187,6 @@ pub enum ActivityKind {
     Competing = 5,
 }
 
-impl Default for ActivityKind {
-    fn default() -> Self {
-        Self::Playing
-    }
-}
-
 /// The activity kinds you can invite a [`User`](crate::user::User) to engage in.
 ///
 /// [API docs](https://discord.com/developers/docs/game-sdk/activities#data-models-activityactiontype-enum)
@@ -254,7 +298,7 @@ pub struct SetActivity {
 }
 
 /// Secret passwords for joining and spectating the player's game
-#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Secrets {
     /// Unique hash for the given match context
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -268,35 +312,33 @@ pub struct Secrets {
 }
 
 /// A clickable button underneath the activity.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Button {
     pub label: String,
     pub url: String,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// A button on an activity. Only the labels of the buttons on the activities
+/// of other users are visible, as the urls are only sent to users that click
+/// them.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum ButtonKind {
     Label(String),
     Link(Button),
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone, Default)]
 pub struct ActivityArgs {
-    pid: u32,
+    /// The process the activity is for, if not set this will be the
+    /// [target pid](crate::Discord::set_target_pid) of the [`Discord`](crate::Discord)
+    /// the activity is sent with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pid: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub activity: Option<Activity>,
 }
 
-impl Default for ActivityArgs {
-    fn default() -> Self {
-        Self {
-            pid: std::process::id(),
-            activity: None,
-        }
-    }
-}
-
 impl From<ActivityBuilder> for ActivityArgs {
     #[inline]
     fn from(ab: ActivityBuilder) -> Self {
@@ -316,13 +358,18 @@ impl ActivityBuilder {
 
     #[cfg(test)]
     pub fn with_pid(pid: u32) -> Self {
-        Self {
-            inner: ActivityArgs {
-                pid,
-                activity: None,
-            },
-        }
+        Self::new().pid(pid)
     }
+
+    /// The process the activity is for, by default this is the
+    /// [target pid](crate::Discord::set_target_pid) of the [`Discord`](crate::Discord)
+    /// the activity is sent with, which is the current process unless
+    /// otherwise specified.
+    pub fn pid(mut self, pid: u32) -> Self {
+        self.inner.pid = Some(pid);
+        self
+    }
+
     /// The user's currenty party status, eg. "Playing Solo".
     ///
     /// Limited to 128 bytes.
@@ -621,7 +668,10 @@ impl crate::Discord {
         &self,
         activity: impl Into<ActivityArgs>,
     ) -> Result<Option<Activity>, Error> {
-        let rx = self.send_rpc(CommandKind::SetActivity, activity.into())?;
+        let mut args = activity.into();
+        args.pid.get_or_insert_with(|| self.target_pid());
+
+        let rx = self.send_rpc(CommandKind::SetActivity, args)?;
 
         // TODO: Keep track of the last set activity and send it immediately if
         // the connection to Discord is lost then reestablished?
@@ -644,6 +694,19 @@ impl crate::Discord {
         user_id: UserId,
         message: impl Into<String>,
         kind: ActivityActionKind,
+    ) -> Result<(), Error> {
+        self.invite_user_for_pid(user_id, message, kind, self.target_pid())
+            .await
+    }
+
+    /// The same as [`Self::invite_user`], but for the activity of the
+    /// specified process rather than the [target pid](Self::set_target_pid).
+    pub async fn invite_user_for_pid(
+        &self,
+        user_id: UserId,
+        message: impl Into<String>,
+        kind: ActivityActionKind,
+        pid: u32,
     ) -> Result<(), Error> {
         #[derive(Serialize)]
         struct Invite {
@@ -657,7 +720,7 @@ impl crate::Discord {
         let rx = self.send_rpc(
             CommandKind::ActivityInviteUser,
             Invite {
-                pid: std::process::id(),
+                pid,
                 user_id,
                 content: message.into(),
                 kind,
@@ -705,11 +768,13 @@ impl crate::Discord {
     ///
     /// [API docs](https://discord.com/developers/docs/game-sdk/activities#clearactivity)
     pub async fn clear_activity(&self) -> Result<Option<Activity>, Error> {
-        let rx = self.send_rpc(CommandKind::SetActivity, ActivityArgs::default())?;
+        self.update_activity(ActivityArgs::default()).await
+    }
 
-        handle_response!(rx, Command::SetActivity(sa) => {
-            Ok(sa.map(|sa| sa.activity))
-        })
+    /// The same as [`Self::clear_activity`], but for the activity of the
+    /// specified process rather than the [target pid](Self::set_target_pid).
+    pub async fn clear_activity_for_pid(&self, pid: u32) -> Result<Option<Activity>, Error> {
+        self.update_activity(ActivityBuilder::new().pid(pid)).await
     }
 
     /// Sends a reply to an [Ask to Join](crate::Event::ActivityJoinRequest) request.
@@ -758,27 +823,18 @@ pub enum ButtonsOrSecrets {
     Secrets { secrets: Secrets },
 }
 
+/// The maximum length, in bytes, of the text fields of an activity
+pub const MAX_TEXT_LEN: usize = 128;
+
 /// All strings in the rich presence info have limits enforced in discord itself
 /// so we just truncate them manually client side to avoid sending more data
 #[inline]
 fn truncate(text: Option<impl Into<String>>, name: &str) -> Option<String> {
     text.and_then(|text| {
         let mut text = text.into();
-        if text.len() > 128 {
+        if text.len() > MAX_TEXT_LEN {
             tracing::warn!("{name} '{text}' is too long and will be truncated");
-
-            // TODO: Just use https://doc.rust-lang.org/std/primitive.str.html#method.floor_char_boundary
-            // when it is stabilized
-            let lower_bound = 125;
-            // Find the highest character boundary between our max and the lowest possible one
-            let new_index = text.as_bytes()[125..=128].iter().rposition(|b| {
-                // This is bit magic equivalent to: b < 128 || b >= 192
-                (*b as i8) >= -0x40
-            });
-
-            text.truncate(
-                lower_bound + new_index.expect("character boundary not found within 4 bytes"),
-            );
+            text.truncate(floor_char_boundary(&text, MAX_TEXT_LEN));
         }
 
         // Ensure the strings don't have just whitespace, as they are also not
@@ -791,6 +847,21 @@ fn truncate(text: Option<impl Into<String>>, name: &str) -> Option<String> {
     })
 }
 
+/// Finds the highest character boundary at or below the index
+#[inline]
+pub(crate) fn floor_char_boundary(s: &str, index: usize) -> usize {
+    // TODO: Just use https://doc.rust-lang.org/std/primitive.str.html#method.floor_char_boundary
+    // when it is stabilized
+    if index >= s.len() {
+        s.len()
+    } else {
+        (0..=index)
+            .rev()
+            .find(|i| s.is_char_boundary(*i))
+            .unwrap_or_default()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -0,0 +1,281 @@
+//! Provides an inbox for keeping track of the [`ActivityInvite`]s the current
+//! user has received, so that they can be presented and accepted at a later time
+
+use super::{events::ActivityEvent, events::InviteEvent, ActivityInvite};
+use crate::{
+    relations::{events::RelationshipEvent, RelationKind},
+    user::UserId,
+    Error,
+};
+use parking_lot::RwLock;
+use std::time::Duration;
+
+/// Uniquely identifies an invite in an [`InviteInbox`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InviteKey {
+    /// The user that sent the invite
+    pub user_id: UserId,
+    /// The unique identifier of the activity the user was invited to
+    pub session_id: String,
+}
+
+impl From<&ActivityInvite> for InviteKey {
+    fn from(invite: &ActivityInvite) -> Self {
+        Self {
+            user_id: invite.user.id,
+            session_id: invite.activity.session_id.clone(),
+        }
+    }
+}
+
+struct Entry {
+    invite: InviteEvent,
+    /// The time the invite was created, or if Discord didn't tell us, the
+    /// time we received it
+    created_at: time::OffsetDateTime,
+}
+
+/// Keeps track of the invites received by the current user.
+///
+/// Invites are keyed by the [`InviteKey`], so only the most recent invite
+/// from a user to a particular session is kept, and invites that are older
+/// than the configured time to live are discarded.
+///
+/// ```no_run
+/// # async fn run(discord: discord_sdk::Discord, wheel: discord_sdk::wheel::Wheel) {
+/// use discord_sdk::activity::invites::{InviteInbox, InviteKey};
+///
+/// let inbox = std::sync::Arc::new(InviteInbox::new(std::time::Duration::from_secs(10 * 60)));
+/// let mut activity = wheel.activity();
+///
+/// let ib = inbox.clone();
+/// tokio::task::spawn(async move {
+///     while let Ok(ae) = activity.0.recv().await {
+///         ib.on_event(ae);
+///     }
+/// });
+///
+/// if let Some(invite) = inbox.list().first() {
+///     let key = InviteKey::from(invite.as_ref());
+///     inbox.accept(&discord, &key).await.unwrap();
+/// }
+/// # }
+/// ```
+pub struct InviteInbox {
+    ttl: Duration,
+    invites: RwLock<Vec<Entry>>,
+}
+
+impl InviteInbox {
+    /// Creates an empty inbox, where invites that were created longer than
+    /// `ttl` ago are discarded
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            invites: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Updates the inbox with an activity event, only
+    /// [`ActivityEvent::Invite`] is relevant, all other events are ignored
+    pub fn on_event(&self, ae: ActivityEvent) {
+        if let ActivityEvent::Invite(invite) = ae {
+            self.insert(invite);
+        }
+    }
+
+    /// Updates the inbox with a relationship event, any invites from users
+    /// that have been blocked are removed
+    pub fn on_relationship_event(&self, re: &RelationshipEvent) {
+        match re {
+            RelationshipEvent::Update(rel) => {
+                if rel.kind == RelationKind::Blocked {
+                    self.invites
+                        .write()
+                        .retain(|entry| entry.invite.0.user.id != rel.user.id);
+                }
+            }
+        }
+    }
+
+    /// Adds an invite to the inbox, superseding any older invite from the
+    /// same user to the same session
+    pub fn insert(&self, invite: InviteEvent) {
+        let created_at = invite
+            .0
+            .activity
+            .created_at
+            .unwrap_or_else(time::OffsetDateTime::now_utc);
+
+        let key = InviteKey::from(invite.0.as_ref());
+        let mut invites = self.invites.write();
+
+        match invites
+            .iter()
+            .position(|entry| Self::matches(&entry.invite.0, &key))
+        {
+            Some(i) => {
+                // Discord can deliver invites out of order, so don't let an
+                // older invite replace a newer one
+                if invites[i].created_at <= created_at {
+                    invites[i] = Entry { invite, created_at };
+                }
+            }
+            None => invites.push(Entry { invite, created_at }),
+        }
+    }
+
+    /// Gets the invites that are currently valid, newest first
+    pub fn list(&self) -> Vec<InviteEvent> {
+        self.prune();
+
+        let invites = self.invites.read();
+        let mut list: Vec<_> = invites.iter().collect();
+        list.sort_by_key(|entry| std::cmp::Reverse(entry.created_at));
+        list.into_iter().map(|entry| entry.invite.clone()).collect()
+    }
+
+    /// Gets the invite matching the key, if it is still valid
+    pub fn get(&self, key: &InviteKey) -> Option<InviteEvent> {
+        self.prune();
+
+        self.invites
+            .read()
+            .iter()
+            .find(|entry| Self::matches(&entry.invite.0, key))
+            .map(|entry| entry.invite.clone())
+    }
+
+    /// Removes the invite matching the key from the inbox without accepting it
+    pub fn dismiss(&self, key: &InviteKey) -> Option<InviteEvent> {
+        let mut invites = self.invites.write();
+        let i = invites
+            .iter()
+            .position(|entry| Self::matches(&entry.invite.0, key))?;
+        Some(invites.swap_remove(i).invite)
+    }
+
+    /// Removes all invites from the inbox
+    pub fn clear(&self) {
+        self.invites.write().clear();
+    }
+
+    /// Accepts the invite matching the key, removing it from the inbox once
+    /// Discord has accepted it. If Discord fails to accept the invite, it is
+    /// kept in the inbox.
+    ///
+    /// # Errors
+    /// Fails with [`Error::InviteUnavailable`] if the invite has expired, been
+    /// superseded, or was never received in the first place
+    pub async fn accept(&self, discord: &crate::Discord, key: &InviteKey) -> Result<(), Error> {
+        let invite = self.get(key).ok_or(Error::InviteUnavailable)?;
+        discord.accept_invite(&invite).await?;
+        self.dismiss(key);
+        Ok(())
+    }
+
+    /// Removes all invites older than the time to live
+    fn prune(&self) {
+        let oldest = time::OffsetDateTime::now_utc() - self.ttl;
+        self.invites
+            .write()
+            .retain(|entry| entry.created_at >= oldest);
+    }
+
+    #[inline]
+    fn matches(invite: &ActivityInvite, key: &InviteKey) -> bool {
+        invite.user.id == key.user_id && invite.activity.session_id == key.session_id
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{activity, types::Snowflake, user::test_user as user};
+    use std::sync::Arc;
+
+    fn invite(user_id: u64, session: &str, created_at: i64) -> InviteEvent {
+        InviteEvent(Arc::new(ActivityInvite {
+            user: user(user_id),
+            activity: activity::InviteActivity {
+                session_id: session.to_owned(),
+                created_at: Some(crate::util::timestamp(created_at)),
+                details: activity::Activity::default(),
+            },
+            kind: activity::ActivityActionKind::Join,
+            channel_id: Snowflake(1),
+            message_id: Snowflake(created_at as u64),
+        }))
+    }
+
+    fn key(user_id: u64, session: &str) -> InviteKey {
+        InviteKey {
+            user_id: Snowflake(user_id),
+            session_id: session.to_owned(),
+        }
+    }
+
+    #[test]
+    fn supersedes() {
+        let inbox = InviteInbox::new(Duration::from_secs(60));
+        let now = time::OffsetDateTime::now_utc().unix_timestamp() * 1000;
+
+        inbox.insert(invite(1, "first", now - 2000));
+        inbox.insert(invite(2, "other", now - 1000));
+        // Same user, different session
+        inbox.insert(invite(1, "second", now));
+        // Same user and session, newer invite
+        inbox.insert(invite(1, "first", now - 500));
+        // Same user and session, but older than what we already have
+        inbox.insert(invite(1, "second", now - 3000));
+
+        let list = inbox.list();
+        assert_eq!(list.len(), 3);
+        assert_eq!(list[0].0.activity.session_id, "second");
+        assert_eq!(list[0].0.message_id, Snowflake(now as u64));
+        assert_eq!(list[1].0.activity.session_id, "first");
+        assert_eq!(list[1].0.message_id, Snowflake((now - 500) as u64));
+        assert_eq!(list[2].0.activity.session_id, "other");
+
+        assert!(inbox.get(&key(1, "zeroth")).is_none());
+        assert!(inbox.dismiss(&key(1, "second")).is_some());
+        assert_eq!(inbox.list().len(), 2);
+    }
+
+    #[test]
+    fn expires() {
+        let inbox = InviteInbox::new(Duration::from_secs(60));
+        let now = time::OffsetDateTime::now_utc().unix_timestamp() * 1000;
+
+        inbox.insert(invite(1, "old", now - 61 * 1000));
+        inbox.insert(invite(2, "new", now - 1000));
+
+        let list = inbox.list();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].0.activity.session_id, "new");
+    }
+
+    #[test]
+    fn blocked() {
+        let inbox = InviteInbox::new(Duration::from_secs(60));
+        let now = time::OffsetDateTime::now_utc().unix_timestamp() * 1000;
+
+        inbox.insert(invite(1, "one", now));
+        inbox.insert(invite(2, "two", now));
+
+        inbox.on_relationship_event(&RelationshipEvent::Update(Arc::new(
+            crate::relations::Relationship {
+                kind: RelationKind::Blocked,
+                user: user(1),
+                presence: crate::relations::RelationshipPresence {
+                    status: crate::relations::RelationStatus::Offline,
+                    activity: None,
+                },
+            },
+        )));
+
+        let list = inbox.list();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].0.user.id, Snowflake(2));
+    }
+}
@@ -0,0 +1,239 @@
+//! Provides a [`JoinFlow`] which ties together the steps required to go from
+//! an invite or join request to actually joining another user's game
+
+use super::events::{ActivityEvent, InviteEvent, SecretEvent};
+use crate::{
+    error::JoinError,
+    user::UserId,
+    wheel::{UserState, Wheel},
+    Error,
+};
+use std::{
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::sync::broadcast;
+
+/// An RPC that needs to succeed before the join secret can be received
+type Action<'dis> = Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'dis>>;
+
+/// A future that resolves with the decoded join secret once Discord has sent
+/// an [`ActivityJoin`](crate::Event::ActivityJoin) event, or a [`JoinError`]
+/// if the join could not be completed.
+///
+/// The join secret is decoded via [`FromStr`], so you can use your own
+/// type for the secret as long as it implements it, or just use [`String`].
+///
+/// If the join secret was received before the flow was created, eg. because
+/// Discord launched the application to join another user's game and the
+/// `ActivityJoin` arrived before anything subscribed to the [`Wheel`], the flow
+/// created with [`JoinFlow::wait`] will still resolve with that secret.
+///
+/// ```no_run
+/// # async fn run(wheel: discord_sdk::wheel::Wheel) {
+/// use discord_sdk::activity::join::JoinFlow;
+///
+/// let secret: String = JoinFlow::wait(&wheel, std::time::Duration::from_secs(10))
+///     .await
+///     .expect("failed to join");
+/// # }
+/// ```
+pub struct JoinFlow<'dis, S = String> {
+    inner: Pin<Box<dyn Future<Output = Result<S, JoinError>> + Send + 'dis>>,
+}
+
+impl<'dis, S> JoinFlow<'dis, S>
+where
+    S: FromStr + Send + 'dis,
+    S::Err: std::fmt::Display,
+{
+    /// Waits for a join secret to be sent by Discord, eg. when the application
+    /// was launched by Discord to join a game, or when the current user asked
+    /// to join another user's game and is waiting for that user's reply.
+    pub fn wait(wheel: &Wheel, timeout: Duration) -> Self {
+        Self::start(wheel, None, Wheel::take_unclaimed_join, timeout)
+    }
+
+    /// Accepts the invite to another user's activity, and then waits for
+    /// Discord to send the join secret for it.
+    ///
+    /// A join secret received before the flow was created is only used if it
+    /// matches the join secret in the invite's activity.
+    ///
+    /// # Errors
+    /// Resolves with [`JoinError::Rejected`] if Discord refused to accept the
+    /// invite, eg. because the invite is no longer valid.
+    pub fn accept_invite(
+        discord: &'dis crate::Discord,
+        wheel: &Wheel,
+        invite: InviteEvent,
+        timeout: Duration,
+    ) -> Self {
+        let secret = match &invite.0.activity.details.buttons_or_secrets {
+            Some(super::ButtonsOrSecrets::Secrets { secrets }) => secrets.join.clone(),
+            _ => None,
+        };
+
+        Self::start(
+            wheel,
+            Some(Box::pin(
+                async move { discord.accept_invite(&invite).await },
+            )),
+            |wheel| {
+                secret
+                    .as_deref()
+                    .and_then(|secret| wheel.take_unclaimed_join_matching(secret))
+            },
+            timeout,
+        )
+    }
+
+    /// Accepts the specified user's request to join the current user's
+    /// activity, and then waits for Discord to send the join secret.
+    ///
+    /// # Errors
+    /// Resolves with [`JoinError::Rejected`] if Discord refused to send the
+    /// reply, eg. because the join request is no longer valid.
+    pub fn accept_join_request(
+        discord: &'dis crate::Discord,
+        wheel: &Wheel,
+        user_id: UserId,
+        timeout: Duration,
+    ) -> Self {
+        Self::start(
+            wheel,
+            Some(Box::pin(async move {
+                discord
+                    .send_join_request_reply(user_id, super::JoinRequestReply::Yes)
+                    .await
+            })),
+            |_wheel| None,
+            timeout,
+        )
+    }
+
+    fn start(
+        wheel: &Wheel,
+        action: Option<Action<'dis>>,
+        claim: impl FnOnce(&Wheel) -> Option<SecretEvent>,
+        timeout: Duration,
+    ) -> Self {
+        // Subscribe before checking for an unclaimed secret so that there is
+        // no window where the secret could arrive and not be seen by us
+        let activity = wheel.activity().0;
+        let user = wheel.user().0;
+        let unclaimed = claim(wheel);
+
+        let inner = Box::pin(async move {
+            let secret =
+                tokio::time::timeout(timeout, Self::run(activity, user, action, unclaimed))
+                    .await
+                    .map_err(|_elapsed| JoinError::TimedOut)??;
+
+            secret
+                .parse()
+                .map_err(|err: S::Err| JoinError::InvalidSecret(err.to_string()))
+        });
+
+        Self { inner }
+    }
+
+    async fn run(
+        mut activity: broadcast::Receiver<ActivityEvent>,
+        mut user: tokio::sync::watch::Receiver<UserState>,
+        action: Option<Action<'dis>>,
+        unclaimed: Option<SecretEvent>,
+    ) -> Result<String, JoinError> {
+        if let Some(action) = action {
+            action.await.map_err(JoinError::Rejected)?;
+        }
+
+        if let Some(secret) = unclaimed {
+            return Ok(secret.secret);
+        }
+
+        loop {
+            tokio::select! {
+                ae = activity.recv() => {
+                    match ae {
+                        Ok(ActivityEvent::Join(secret)) => return Ok(secret.secret),
+                        Ok(_) => {}
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            tracing::warn!(skipped, "join flow lagged behind activity events");
+                        }
+                        Err(broadcast::error::RecvError::Closed) => {
+                            return Err(JoinError::Disconnected(
+                                "the wheel handler was dropped".to_owned(),
+                            ));
+                        }
+                    }
+                }
+                changed = user.changed() => {
+                    if changed.is_err() {
+                        return Err(JoinError::Disconnected(
+                            "the wheel handler was dropped".to_owned(),
+                        ));
+                    }
+
+                    if let UserState::Disconnected(err) = &*user.borrow_and_update() {
+                        return Err(JoinError::Disconnected(err.to_string()));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<S> Future for JoinFlow<'_, S> {
+    type Output = Result<S, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{activity::events::SecretEvent, DiscordHandler, DiscordMsg, Event};
+
+    #[tokio::test]
+    async fn unclaimed() {
+        let (wheel, handler) = Wheel::new(Box::new(|_err| {}));
+
+        handler
+            .on_message(DiscordMsg::Event(Event::ActivityJoin(SecretEvent {
+                secret: "42".to_owned(),
+            })))
+            .await;
+
+        let secret: u32 = JoinFlow::wait(&wheel, Duration::from_millis(10))
+            .await
+            .unwrap();
+        assert_eq!(secret, 42);
+
+        // The secret can only be claimed once
+        assert!(matches!(
+            JoinFlow::<String>::wait(&wheel, Duration::from_millis(10)).await,
+            Err(JoinError::TimedOut)
+        ));
+    }
+
+    #[tokio::test]
+    async fn disconnected() {
+        let (wheel, handler) = Wheel::new(Box::new(|_err| {}));
+
+        let flow = JoinFlow::<String>::wait(&wheel, Duration::from_secs(10));
+
+        handler
+            .on_message(DiscordMsg::Event(Event::Disconnected {
+                reason: Error::NoConnection,
+            }))
+            .await;
+
+        assert!(matches!(flow.await, Err(JoinError::Disconnected(_))));
+    }
+}
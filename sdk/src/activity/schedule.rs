@@ -0,0 +1,440 @@
+//! Provides a [`PresenceSchedule`] for rotating between several activities on
+//! a timer, as well as temporarily overriding them
+
+use super::ActivityArgs;
+use crate::Error;
+use parking_lot::Mutex;
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use tokio::time::Instant;
+
+/// The maximum number of activity updates Discord allows within [`RATE_WINDOW`]
+const RATE_LIMIT: usize = 5;
+/// The window of time in which at most [`RATE_LIMIT`] updates can be sent
+const RATE_WINDOW: Duration = Duration::from_secs(20);
+
+/// Identifies an override added via [`PresenceSchedule::push_override`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct OverrideId(u64);
+
+struct Entry {
+    args: ActivityArgs,
+    duration: Duration,
+}
+
+struct Override {
+    id: OverrideId,
+    args: ActivityArgs,
+    expires: Instant,
+    priority: i32,
+}
+
+/// What is currently being shown, so that we only send updates when it changes
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Shown {
+    Nothing,
+    Rotation(usize),
+    Override(OverrideId),
+}
+
+#[derive(Default)]
+struct Inner {
+    rotation: Vec<Entry>,
+    /// The index of the current rotation entry, and when it started being shown
+    position: Option<(usize, Instant)>,
+    overrides: Vec<Override>,
+    next_id: u64,
+    /// What was last sent to Discord, `None` if nothing has been sent yet, or
+    /// the schedule has been changed and needs to be resent
+    shown: Option<Shown>,
+    /// The times of the most recent updates, used to stay within the rate limit
+    sent: VecDeque<Instant>,
+}
+
+impl Inner {
+    /// Determines what should be shown at the specified time, as well as the
+    /// next time that could change
+    fn select(&mut self, now: Instant) -> (Shown, Option<Instant>) {
+        self.overrides.retain(|o| o.expires > now);
+
+        if let Some(ovr) = self.overrides.iter().max_by_key(|o| (o.priority, o.id.0)) {
+            let next = self.overrides.iter().map(|o| o.expires).min();
+            return (Shown::Override(ovr.id), next);
+        }
+
+        if self.rotation.is_empty() {
+            return (Shown::Nothing, None);
+        }
+
+        let (mut index, started) = self.position.unwrap_or((0, now));
+
+        // Skip whole cycles if we've fallen far behind, eg. because the
+        // schedule was not being driven for a while, so that we only need to
+        // walk through at most one cycle
+        let cycle: u128 = self.rotation.iter().map(|e| e.duration.as_nanos()).sum();
+        let mut offset = (now - started).as_nanos() % cycle;
+
+        while offset >= self.rotation[index].duration.as_nanos() {
+            offset -= self.rotation[index].duration.as_nanos();
+            index = (index + 1) % self.rotation.len();
+        }
+
+        // The offset is less than the duration of a single entry
+        let started = now
+            - Duration::new(
+                (offset / 1_000_000_000) as u64,
+                (offset % 1_000_000_000) as u32,
+            );
+
+        self.position = Some((index, started));
+        (
+            Shown::Rotation(index),
+            Some(started + self.rotation[index].duration),
+        )
+    }
+
+    /// The earliest time the next update can be sent without exceeding the
+    /// rate limit
+    fn next_allowed(&mut self, now: Instant) -> Instant {
+        while self
+            .sent
+            .front()
+            .is_some_and(|sent| *sent + RATE_WINDOW <= now)
+        {
+            self.sent.pop_front();
+        }
+
+        if self.sent.len() < RATE_LIMIT {
+            now
+        } else {
+            self.sent[self.sent.len() - RATE_LIMIT] + RATE_WINDOW
+        }
+    }
+
+    fn args(&self, shown: Shown) -> ActivityArgs {
+        match shown {
+            Shown::Nothing => ActivityArgs::default(),
+            Shown::Rotation(i) => self.rotation[i].args.clone(),
+            Shown::Override(id) => self
+                .overrides
+                .iter()
+                .find(|o| o.id == id)
+                .map(|o| o.args.clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// A schedule of activities that is driven by
+/// [`Discord::drive_presence`](crate::Discord::drive_presence).
+///
+/// The schedule rotates through a list of activities, each shown for its own
+/// duration, eg. "In menu" → "Looking for group" → "Season 4 rank: Gold".
+/// Temporary overrides can be pushed on top of the rotation, the override with
+/// the highest priority is shown until it expires, at which point the schedule
+/// reverts to the next highest priority override, or the rotation.
+///
+/// Updates are throttled to stay within Discord's rate limit of 5 updates per
+/// 20 seconds, so rotating faster than every 4 seconds will cause activities to
+/// be shown for longer than their specified duration.
+///
+/// ```no_run
+/// # async fn run(discord: std::sync::Arc<discord_sdk::Discord>) {
+/// use discord_sdk::activity::{schedule::PresenceSchedule, ActivityBuilder};
+/// use std::time::Duration;
+///
+/// let schedule = std::sync::Arc::new(PresenceSchedule::new());
+/// schedule.set_rotation([
+///     (ActivityBuilder::new().state("In menu"), Duration::from_secs(30)),
+///     (ActivityBuilder::new().state("Looking for group"), Duration::from_secs(30)),
+/// ]).expect("rotation has no zero length entries");
+///
+/// let sched = schedule.clone();
+/// let dis = discord.clone();
+/// tokio::task::spawn(async move { dis.drive_presence(&sched).await });
+///
+/// schedule.push_override(
+///     ActivityBuilder::new().state("Match found!"),
+///     Duration::from_secs(10),
+///     1,
+/// );
+/// # }
+/// ```
+#[derive(Default)]
+pub struct PresenceSchedule {
+    inner: Mutex<Inner>,
+    changed: tokio::sync::Notify,
+    stopped: AtomicBool,
+}
+
+impl PresenceSchedule {
+    /// Creates an empty schedule, which doesn't show anything until a
+    /// rotation is set or an override is pushed
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the activities that are rotated through, each activity is
+    /// shown for its respective duration
+    ///
+    /// # Errors
+    /// Fails with [`Error::ZeroLengthEntry`] if any of the activities has a
+    /// duration of zero, in which case the current rotation is left as is
+    pub fn set_rotation<A>(
+        &self,
+        rotation: impl IntoIterator<Item = (A, Duration)>,
+    ) -> Result<(), Error>
+    where
+        A: Into<ActivityArgs>,
+    {
+        let rotation = rotation
+            .into_iter()
+            .enumerate()
+            .map(|(i, (args, duration))| {
+                if duration.is_zero() {
+                    Err(Error::ZeroLengthEntry(i))
+                } else {
+                    Ok(Entry {
+                        args: args.into(),
+                        duration,
+                    })
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        {
+            let mut inner = self.inner.lock();
+            inner.rotation = rotation;
+            inner.position = None;
+            inner.shown = None;
+        }
+
+        self.changed.notify_one();
+        Ok(())
+    }
+
+    /// Temporarily shows the activity for the specified duration, instead of
+    /// the rotation or any lower priority overrides
+    pub fn push_override(
+        &self,
+        activity: impl Into<ActivityArgs>,
+        duration: Duration,
+        priority: i32,
+    ) -> OverrideId {
+        let id = {
+            let mut inner = self.inner.lock();
+            let id = OverrideId(inner.next_id);
+            inner.next_id += 1;
+            inner.overrides.push(Override {
+                id,
+                args: activity.into(),
+                expires: Instant::now() + duration,
+                priority,
+            });
+            id
+        };
+
+        self.changed.notify_one();
+        id
+    }
+
+    /// Removes an override before it expires
+    pub fn remove_override(&self, id: OverrideId) {
+        self.inner.lock().overrides.retain(|o| o.id != id);
+        self.changed.notify_one();
+    }
+
+    /// Stops [`Discord::drive_presence`](crate::Discord::drive_presence), the
+    /// last activity that was set is left as is
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Release);
+        self.changed.notify_one();
+    }
+}
+
+impl crate::Discord {
+    /// Drives the presence schedule, updating the current user's activity as
+    /// the schedule dictates, until [`PresenceSchedule::stop`] is called.
+    ///
+    /// Failures to update the activity are logged and the update is retried
+    /// the next time the schedule changes.
+    pub async fn drive_presence(&self, schedule: &PresenceSchedule) {
+        while !schedule.stopped.load(Ordering::Acquire) {
+            let (update, wake) = {
+                let mut inner = schedule.inner.lock();
+                let now = Instant::now();
+                let (shown, next_change) = inner.select(now);
+
+                if inner.shown == Some(shown) {
+                    (None, next_change)
+                } else {
+                    let allowed = inner.next_allowed(now);
+                    if allowed <= now {
+                        inner.sent.push_back(now);
+                        inner.shown = Some(shown);
+                        (Some(inner.args(shown)), next_change)
+                    } else {
+                        (None, Some(allowed))
+                    }
+                }
+            };
+
+            if let Some(args) = update {
+                if let Err(err) = self.update_activity(args).await {
+                    tracing::warn!(error = %err, "failed to update scheduled activity");
+                    schedule.inner.lock().shown = None;
+                }
+
+                continue;
+            }
+
+            match wake {
+                Some(wake) => {
+                    let _ = tokio::time::timeout_at(wake, schedule.changed.notified()).await;
+                }
+                None => schedule.changed.notified().await,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::activity::ActivityBuilder;
+
+    fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    #[test]
+    fn rotates() {
+        let mut inner = Inner {
+            rotation: ["one", "two", "three"]
+                .into_iter()
+                .map(|state| Entry {
+                    args: ActivityBuilder::with_pid(1).state(state).into(),
+                    duration: secs(10),
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        let start = Instant::now();
+        assert_eq!(
+            inner.select(start),
+            (Shown::Rotation(0), Some(start + secs(10)))
+        );
+        assert_eq!(
+            inner.select(start + secs(15)),
+            (Shown::Rotation(1), Some(start + secs(20)))
+        );
+        assert_eq!(
+            inner.select(start + secs(35)),
+            (Shown::Rotation(0), Some(start + secs(40)))
+        );
+        assert_eq!(
+            inner.select(start + secs(1000)),
+            (Shown::Rotation(1), Some(start + secs(1010)))
+        );
+    }
+
+    #[test]
+    fn skips_cycles() {
+        let mut inner = Inner {
+            rotation: [1, 2, 3]
+                .into_iter()
+                .map(|nanos| Entry {
+                    args: ActivityBuilder::with_pid(1).into(),
+                    duration: Duration::from_nanos(nanos),
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        let start = Instant::now();
+        assert_eq!(
+            inner.select(start),
+            (Shown::Rotation(0), Some(start + Duration::from_nanos(1)))
+        );
+
+        // Falling behind by more cycles than fit in a u32 still lands on the
+        // correct entry
+        let later = start + Duration::from_nanos(6 * (u64::from(u32::MAX) + 1) + 4);
+        assert_eq!(
+            inner.select(later),
+            (Shown::Rotation(2), Some(later + Duration::from_nanos(2)))
+        );
+    }
+
+    #[test]
+    fn rejects_zero_length() {
+        let schedule = PresenceSchedule::new();
+        schedule
+            .set_rotation([(ActivityBuilder::with_pid(1).state("one"), secs(10))])
+            .unwrap();
+
+        assert!(matches!(
+            schedule.set_rotation([
+                (ActivityBuilder::with_pid(1).state("one"), secs(10)),
+                (ActivityBuilder::with_pid(1).state("two"), Duration::ZERO),
+            ]),
+            Err(Error::ZeroLengthEntry(1))
+        ));
+        assert_eq!(schedule.inner.lock().rotation.len(), 1);
+    }
+
+    #[test]
+    fn overrides() {
+        let mut inner = Inner {
+            rotation: vec![Entry {
+                args: ActivityBuilder::with_pid(1).state("rotation").into(),
+                duration: secs(60),
+            }],
+            ..Default::default()
+        };
+
+        let start = Instant::now();
+        for (id, priority, expires) in [(0, 1, 10), (1, 2, 5), (2, 1, 20)] {
+            inner.overrides.push(Override {
+                id: OverrideId(id),
+                args: ActivityArgs::default(),
+                expires: start + secs(expires),
+                priority,
+            });
+        }
+
+        assert_eq!(
+            inner.select(start),
+            (Shown::Override(OverrideId(1)), Some(start + secs(5)))
+        );
+        // Equal priorities prefer the most recent override
+        assert_eq!(
+            inner.select(start + secs(5)),
+            (Shown::Override(OverrideId(2)), Some(start + secs(10)))
+        );
+        // The rotation starts once it is first shown
+        assert_eq!(
+            inner.select(start + secs(20)),
+            (Shown::Rotation(0), Some(start + secs(80)))
+        );
+    }
+
+    #[test]
+    fn rate_limits() {
+        let mut inner = Inner::default();
+        let start = Instant::now();
+
+        for i in 0..RATE_LIMIT as u64 {
+            let now = start + secs(i);
+            assert_eq!(inner.next_allowed(now), now);
+            inner.sent.push_back(now);
+        }
+
+        assert_eq!(inner.next_allowed(start + secs(5)), start + RATE_WINDOW);
+        assert_eq!(inner.next_allowed(start + RATE_WINDOW), start + RATE_WINDOW);
+    }
+}
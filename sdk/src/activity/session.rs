@@ -0,0 +1,292 @@
+//! Provides [`MatchPresence`], which keeps the current user's activity up to
+//! date over the lifetime of a match
+
+use super::{Activity, ActivityArgs, ActivityBuilder, IntoTimestamp, PartyPrivacy, Secrets};
+use crate::Error;
+use std::num::NonZeroU32;
+
+struct Match {
+    party_id: String,
+    privacy: PartyPrivacy,
+    secrets: Secrets,
+    /// The unix timestamp the match was started at
+    started_at: i64,
+    size: NonZeroU32,
+    max_size: NonZeroU32,
+    phase: Option<String>,
+}
+
+impl Match {
+    fn add_player(&mut self) {
+        if self.size < self.max_size {
+            self.size = self.size.saturating_add(1);
+        } else {
+            tracing::warn!("a player joined a party that was already full");
+        }
+    }
+
+    fn remove_player(&mut self) {
+        // The local player is always part of the party
+        if let Some(size) = NonZeroU32::new(self.size.get() - 1) {
+            self.size = size;
+        }
+    }
+}
+
+/// Manages the sequence of activity updates that most games go through during
+/// a match. The match is started with the local player as the only member of
+/// the party, players are added and removed as they come and go, and finally
+/// the match is ended, restoring the activity to what it was before the match.
+///
+/// Every method pushes the new activity to Discord via
+/// [`Discord::update_activity`](crate::Discord::update_activity), so keep in
+/// mind the rate limit of 5 updates per 20 seconds.
+///
+/// ```no_run
+/// # async fn run(discord: discord_sdk::Discord) -> Result<(), discord_sdk::Error> {
+/// use discord_sdk::activity::{session::MatchPresence, ActivityBuilder, PartyPrivacy, Secrets};
+///
+/// let mut presence = MatchPresence::new(ActivityBuilder::new().state("In menu"));
+///
+/// presence
+///     .start_match(
+///         &discord,
+///         "match-1234",
+///         std::num::NonZeroU32::new(4).unwrap(),
+///         PartyPrivacy::Public,
+///         Secrets {
+///             join: Some("join-1234".to_owned()),
+///             ..Default::default()
+///         },
+///     )
+///     .await?;
+/// presence.player_joined(&discord).await?;
+/// presence.set_phase(&discord, "Round 2").await?;
+/// presence.end_match(&discord).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct MatchPresence {
+    /// The activity to use outside of a match, and as the base for the
+    /// activity during a match
+    base: ActivityArgs,
+    current: Option<Match>,
+}
+
+impl MatchPresence {
+    /// Creates a new match presence, the provided activity is the one that is
+    /// shown when the user is not in a match, and is used as the basis for
+    /// the activity during a match.
+    pub fn new(base: impl Into<ActivityArgs>) -> Self {
+        Self {
+            base: base.into(),
+            current: None,
+        }
+    }
+
+    /// Whether a match is currently in progress
+    #[inline]
+    pub fn in_match(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// The current and maximum size of the party, if a match is in progress
+    pub fn party_size(&self) -> Option<(u32, u32)> {
+        self.current
+            .as_ref()
+            .map(|cur| (cur.size.get(), cur.max_size.get()))
+    }
+
+    /// Starts a new match, with the local player as the only member of the
+    /// party, replacing any match that was already in progress.
+    ///
+    /// Note that the party id is limited to 128 bytes.
+    pub async fn start_match(
+        &mut self,
+        discord: &crate::Discord,
+        party_id: impl Into<String>,
+        max_size: NonZeroU32,
+        privacy: PartyPrivacy,
+        secrets: Secrets,
+    ) -> Result<Option<Activity>, Error> {
+        self.current = Some(Match {
+            party_id: party_id.into(),
+            privacy,
+            secrets,
+            started_at: std::time::SystemTime::now().into_timestamp(),
+            size: NonZeroU32::MIN,
+            max_size,
+            phase: None,
+        });
+
+        self.push(discord).await
+    }
+
+    /// Adds a player to the party. The party size is never increased past
+    /// the maximum size of the party.
+    ///
+    /// # Errors
+    /// Fails with [`Error::NoActiveMatch`] if a match has not been started
+    pub async fn player_joined(
+        &mut self,
+        discord: &crate::Discord,
+    ) -> Result<Option<Activity>, Error> {
+        self.current
+            .as_mut()
+            .ok_or(Error::NoActiveMatch)?
+            .add_player();
+
+        self.push(discord).await
+    }
+
+    /// Removes a player from the party. The local player is always counted as
+    /// a member of the party, so the size never goes below 1.
+    ///
+    /// # Errors
+    /// Fails with [`Error::NoActiveMatch`] if a match has not been started
+    pub async fn player_left(
+        &mut self,
+        discord: &crate::Discord,
+    ) -> Result<Option<Activity>, Error> {
+        self.current
+            .as_mut()
+            .ok_or(Error::NoActiveMatch)?
+            .remove_player();
+
+        self.push(discord).await
+    }
+
+    /// Sets what the player is currently doing in the match, eg. "Round 2",
+    /// which is shown as the activity's details.
+    ///
+    /// Limited to 128 bytes.
+    ///
+    /// # Errors
+    /// Fails with [`Error::NoActiveMatch`] if a match has not been started
+    pub async fn set_phase(
+        &mut self,
+        discord: &crate::Discord,
+        phase: impl Into<String>,
+    ) -> Result<Option<Activity>, Error> {
+        let cur = self.current.as_mut().ok_or(Error::NoActiveMatch)?;
+        cur.phase = Some(phase.into());
+
+        self.push(discord).await
+    }
+
+    /// Ends the current match, restoring the activity that was provided in
+    /// [`MatchPresence::new`], or clearing it if that activity was empty.
+    pub async fn end_match(&mut self, discord: &crate::Discord) -> Result<Option<Activity>, Error> {
+        self.current = None;
+        self.push(discord).await
+    }
+
+    /// Builds the activity for the current state of the match
+    fn activity(&self) -> ActivityArgs {
+        let Some(cur) = &self.current else {
+            return self.base.clone();
+        };
+
+        let mut builder = ActivityBuilder {
+            inner: self.base.clone(),
+        }
+        .party(
+            cur.party_id.clone(),
+            Some(cur.size),
+            Some(cur.max_size),
+            cur.privacy,
+        )
+        .instance(true)
+        .secrets(cur.secrets.clone());
+
+        if let Some(phase) = &cur.phase {
+            builder = builder.details(phase.clone());
+        }
+
+        // The base activity might have an end timestamp that is before the
+        // start of the match, which Discord won't accept
+        if let Some(timestamps) = builder
+            .inner
+            .activity
+            .as_mut()
+            .and_then(|activity| activity.timestamps.as_mut())
+        {
+            timestamps.start = Some(cur.started_at);
+
+            if timestamps.end.is_some_and(|end| end < cur.started_at) {
+                timestamps.end = None;
+            }
+        } else {
+            builder = builder.start_timestamp(cur.started_at);
+        }
+
+        builder.into()
+    }
+
+    #[inline]
+    async fn push(&self, discord: &crate::Discord) -> Result<Option<Activity>, Error> {
+        // An empty activity is equivalent to clearing it
+        discord.update_activity(self.activity()).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn size(presence: &MatchPresence) -> Option<(u32, u32)> {
+        presence
+            .activity()
+            .activity
+            .and_then(|activity| activity.party)
+            .and_then(|party| party.size)
+    }
+
+    #[test]
+    fn consistent() {
+        let mut presence = MatchPresence::new(
+            ActivityBuilder::with_pid(9999)
+                .state("In menu")
+                .end_timestamp(1000),
+        );
+
+        assert!(presence.activity().activity.unwrap().party.is_none());
+
+        presence.current = Some(Match {
+            party_id: "party".to_owned(),
+            privacy: PartyPrivacy::Private,
+            secrets: Secrets::default(),
+            started_at: 2000,
+            size: NonZeroU32::new(2).unwrap(),
+            max_size: NonZeroU32::new(2).unwrap(),
+            phase: Some("Round 1".to_owned()),
+        });
+
+        let activity = presence.activity().activity.unwrap();
+        assert!(activity.instance);
+        assert_eq!(activity.state.as_deref(), Some("In menu"));
+        assert_eq!(activity.details.as_deref(), Some("Round 1"));
+
+        let timestamps = activity.timestamps.unwrap();
+        assert_eq!(timestamps.start, Some(2000));
+        assert_eq!(timestamps.end, None);
+
+        assert_eq!(size(&presence), Some((2, 2)));
+
+        // The party size can never go above the max or below 1
+        let cur = presence.current.as_mut().unwrap();
+        cur.add_player();
+        assert_eq!(size(&presence), Some((2, 2)));
+
+        let cur = presence.current.as_mut().unwrap();
+        cur.remove_player();
+        cur.remove_player();
+        cur.remove_player();
+        assert_eq!(size(&presence), Some((1, 2)));
+
+        presence.current = None;
+        let activity = presence.activity().activity.unwrap();
+        assert!(activity.party.is_none());
+        assert_eq!(activity.timestamps.unwrap().end, Some(1000));
+    }
+}
@@ -0,0 +1,367 @@
+//! Provides templated, localizable strings for the text fields of an
+//! [`Activity`](super::Activity)
+
+use super::{floor_char_boundary, ActivityBuilder, MAX_TEXT_LEN};
+use crate::error::TemplateError;
+use std::collections::HashMap;
+
+/// The character appended to a variable that had to be truncated
+const ELLIPSIS: char = '…';
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    /// Fixed text that is always part of the output
+    Text(String),
+    /// A named placeholder that is substituted with a variable
+    Var(String),
+}
+
+/// A string with named placeholders, eg. `"{map} – {mode}"`, that are
+/// substituted when the template is rendered.
+///
+/// Literal braces can be escaped by doubling them, eg. `"{{not a placeholder}}"`.
+///
+/// ```
+/// use discord_sdk::activity::template::Template;
+///
+/// let template: Template = "{map} – {mode}".parse().unwrap();
+/// assert_eq!(
+///     template.render(&[("map", "Dust"), ("mode", "Deathmatch")], 128).unwrap(),
+///     "Dust – Deathmatch"
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl std::str::FromStr for Template {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = s.char_indices().peekable();
+
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '{' => {
+                    if chars.next_if(|(_, c)| *c == '{').is_some() {
+                        text.push('{');
+                        continue;
+                    }
+
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '}')) => break,
+                            Some((_, c)) if c.is_alphanumeric() || c == '_' || c == '-' => {
+                                name.push(c);
+                            }
+                            Some((offset, _)) => {
+                                return Err(TemplateError::InvalidPlaceholder { offset })
+                            }
+                            None => return Err(TemplateError::UnclosedPlaceholder { offset }),
+                        }
+                    }
+
+                    if name.is_empty() {
+                        return Err(TemplateError::InvalidPlaceholder { offset });
+                    }
+
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+
+                    segments.push(Segment::Var(name));
+                }
+                '}' => {
+                    if chars.next_if(|(_, c)| *c == '}').is_none() {
+                        return Err(TemplateError::UnmatchedBrace { offset });
+                    }
+
+                    text.push('}');
+                }
+                c => text.push(c),
+            }
+        }
+
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+
+        Ok(Self { segments })
+    }
+}
+
+impl Template {
+    /// The names of the placeholders in the template
+    pub fn placeholders(&self) -> impl Iterator<Item = &str> {
+        self.segments.iter().filter_map(|seg| match seg {
+            Segment::Var(name) => Some(name.as_str()),
+            Segment::Text(_) => None,
+        })
+    }
+
+    /// Renders the template, substituting each placeholder with the variable
+    /// of the same name.
+    ///
+    /// If the rendered string would be longer than `max_len` bytes, the
+    /// variables are shortened first, starting with the longest, and only if
+    /// the fixed text of the template is itself too long is it truncated.
+    ///
+    /// # Errors
+    /// Fails if the template contains a placeholder that doesn't have a
+    /// corresponding variable
+    pub fn render(&self, vars: &[(&str, &str)], max_len: usize) -> Result<String, TemplateError> {
+        let mut fixed_len = 0;
+        let mut values = Vec::new();
+
+        for seg in &self.segments {
+            match seg {
+                Segment::Text(text) => fixed_len += text.len(),
+                Segment::Var(name) => {
+                    let value = vars
+                        .iter()
+                        .find_map(|(key, value)| (key == name).then_some(*value))
+                        .ok_or_else(|| TemplateError::MissingVariable(name.clone()))?;
+                    values.push(value);
+                }
+            }
+        }
+
+        let budget = max_len.saturating_sub(fixed_len);
+        let cap = variable_cap(&values, budget);
+
+        let mut rendered = String::with_capacity(max_len.min(fixed_len + budget));
+        let mut values = values.into_iter();
+
+        for seg in &self.segments {
+            match seg {
+                Segment::Text(text) => rendered.push_str(text),
+                Segment::Var(_) => {
+                    let value = values.next().unwrap_or_default();
+                    shorten_into(value, cap, &mut rendered);
+                }
+            }
+        }
+
+        // If the fixed text alone is too long there's nothing for it but to
+        // cut it off
+        if rendered.len() > max_len {
+            rendered.truncate(floor_char_boundary(&rendered, max_len));
+        }
+
+        Ok(rendered)
+    }
+}
+
+/// Calculates the maximum length each variable can have so that all of them
+/// fit within the budget, shortening the longest variables first
+fn variable_cap(values: &[&str], budget: usize) -> usize {
+    let total: usize = values.iter().map(|v| v.len()).sum();
+    if total <= budget {
+        return usize::MAX;
+    }
+
+    let mut lens: Vec<_> = values.iter().map(|v| v.len()).collect();
+    lens.sort_unstable();
+
+    // Find the highest cap where the sum of the lengths, with every length
+    // clamped to the cap, is still within the budget
+    let mut remaining = budget;
+    let mut count = lens.len();
+    for len in lens {
+        let share = remaining / count;
+        if len > share {
+            return share;
+        }
+
+        remaining -= len;
+        count -= 1;
+    }
+
+    usize::MAX
+}
+
+/// Appends the value to the output, shortening it with an ellipsis if it is
+/// longer than the cap
+fn shorten_into(value: &str, cap: usize, output: &mut String) {
+    if value.len() <= cap {
+        output.push_str(value);
+        return;
+    }
+
+    let ellipsis = ELLIPSIS.len_utf8();
+    if cap > ellipsis {
+        output.push_str(value[..floor_char_boundary(value, cap - ellipsis)].trim_end());
+        output.push(ELLIPSIS);
+    } else {
+        output.push_str(&value[..floor_char_boundary(value, cap)]);
+    }
+}
+
+/// A set of per-locale [`Template`]s, keyed by name
+///
+/// ```
+/// use discord_sdk::activity::template::StringTable;
+///
+/// let mut table = StringTable::new("en");
+/// table.insert("en", "in-match", "{map} – {mode}").unwrap();
+/// table.insert("de", "in-match", "{mode} auf {map}").unwrap();
+///
+/// let vars = [("map", "Dust"), ("mode", "Deathmatch")];
+/// assert_eq!(table.render("de-AT", "in-match", &vars).unwrap(), "Deathmatch auf Dust");
+/// assert_eq!(table.render("fr", "in-match", &vars).unwrap(), "Dust – Deathmatch");
+/// ```
+#[derive(Clone, Debug)]
+pub struct StringTable {
+    fallback: String,
+    locales: HashMap<String, HashMap<String, Template>>,
+}
+
+impl StringTable {
+    /// Creates an empty table, templates that are not available in the
+    /// requested locale are retrieved from the `fallback` locale instead
+    pub fn new(fallback: impl Into<String>) -> Self {
+        Self {
+            fallback: fallback.into(),
+            locales: HashMap::new(),
+        }
+    }
+
+    /// Parses and adds a template for the specified locale
+    pub fn insert(
+        &mut self,
+        locale: impl Into<String>,
+        key: impl Into<String>,
+        template: &str,
+    ) -> Result<(), TemplateError> {
+        let template = template.parse()?;
+        self.locales
+            .entry(locale.into())
+            .or_default()
+            .insert(key.into(), template);
+        Ok(())
+    }
+
+    /// Gets the template for the key, in order of preference, from the exact
+    /// locale, eg. `en-US`, the language of the locale, eg. `en`, or the
+    /// fallback locale
+    pub fn get(&self, locale: &str, key: &str) -> Option<&Template> {
+        let lookup = |locale: &str| self.locales.get(locale).and_then(|t| t.get(key));
+
+        lookup(locale)
+            .or_else(|| {
+                locale
+                    .split_once(['-', '_'])
+                    .and_then(|(lang, _)| lookup(lang))
+            })
+            .or_else(|| lookup(&self.fallback))
+    }
+
+    /// Renders the template for the key in the specified locale, limited to
+    /// [`MAX_TEXT_LEN`] bytes
+    pub fn render(
+        &self,
+        locale: &str,
+        key: &str,
+        vars: &[(&str, &str)],
+    ) -> Result<String, TemplateError> {
+        self.get(locale, key)
+            .ok_or_else(|| TemplateError::UnknownKey(key.to_owned()))?
+            .render(vars, MAX_TEXT_LEN)
+    }
+}
+
+impl ActivityBuilder {
+    /// Renders the template and uses it as the [state](Self::state) of the
+    /// activity. Variables are shortened as needed to fit within the
+    /// [`MAX_TEXT_LEN`] byte limit, see [`Template::render`].
+    pub fn state_template(self, template: &Template, vars: &[(&str, &str)]) -> Self {
+        match template.render(vars, MAX_TEXT_LEN) {
+            Ok(state) => self.state(state),
+            Err(err) => {
+                tracing::warn!(error = %err, "State template could not be rendered, disregarding");
+                self
+            }
+        }
+    }
+
+    /// Renders the template and uses it as the [details](Self::details) of
+    /// the activity. Variables are shortened as needed to fit within the
+    /// [`MAX_TEXT_LEN`] byte limit, see [`Template::render`].
+    pub fn details_template(self, template: &Template, vars: &[(&str, &str)]) -> Self {
+        match template.render(vars, MAX_TEXT_LEN) {
+            Ok(details) => self.details(details),
+            Err(err) => {
+                tracing::warn!(error = %err, "Details template could not be rendered, disregarding");
+                self
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses() {
+        let template: Template = "{{literal}} {map}: {mode}".parse().unwrap();
+        assert_eq!(
+            template.segments,
+            vec![
+                Segment::Text("{literal} ".to_owned()),
+                Segment::Var("map".to_owned()),
+                Segment::Text(": ".to_owned()),
+                Segment::Var("mode".to_owned()),
+            ]
+        );
+
+        assert!(matches!(
+            "{map".parse::<Template>(),
+            Err(TemplateError::UnclosedPlaceholder { offset: 0 })
+        ));
+        assert!(matches!(
+            "map}".parse::<Template>(),
+            Err(TemplateError::UnmatchedBrace { offset: 3 })
+        ));
+        assert!(matches!(
+            "{}".parse::<Template>(),
+            Err(TemplateError::InvalidPlaceholder { offset: 0 })
+        ));
+    }
+
+    #[test]
+    fn shortens_variables() {
+        let template: Template = "{map} – {mode}".parse().unwrap();
+
+        // Only the longest variable is shortened if that is enough
+        let rendered = template
+            .render(&[("map", "Dust"), ("mode", "Capture the flag")], 20)
+            .unwrap();
+        assert_eq!(rendered, "Dust – Capture…");
+        assert!(rendered.len() <= 20);
+
+        // Multibyte characters are never split
+        let rendered = template
+            .render(&[("map", "ääääää"), ("mode", "öööööö")], 16)
+            .unwrap();
+        assert_eq!(rendered, "ä… – ö…");
+        assert!(rendered.len() <= 16);
+
+        assert!(matches!(
+            template.render(&[("map", "Dust")], 128),
+            Err(TemplateError::MissingVariable(name)) if name == "mode"
+        ));
+    }
+
+    #[test]
+    fn truncates_fixed_text() {
+        let template: Template = "{map} is a very long piece of fixed text".parse().unwrap();
+
+        let rendered = template.render(&[("map", "Dust")], 16).unwrap();
+        assert_eq!(rendered, " is a very long ");
+    }
+}
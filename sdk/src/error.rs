@@ -34,6 +34,75 @@ pub enum Error {
     NonCanonicalLobbyActivitySecret,
     #[error("an asynchronous operation did not complete in the allotted time")]
     TimedOut,
+    #[error("the invite has expired, been superseded, or was never received")]
+    InviteUnavailable,
+    #[error("a match has not been started")]
+    NoActiveMatch,
+    #[error("image size '{0}' is not a power of 2 between 16 and 4096")]
+    InvalidImageSize(u32),
+    #[error("the overlay is not available, so the call was not sent to Discord")]
+    OverlayUnavailable,
+    #[error(transparent)]
+    InvalidInviteCode(#[from] InviteCodeError),
+    #[error("entry {0} of the presence rotation has a duration of zero")]
+    ZeroLengthEntry(usize),
+    #[error("a handler panicked while handling {msg}: {panic}")]
+    HandlerPanicked { msg: &'static str, panic: String },
+    /// An error that is shared between several handlers, see
+    /// [`FanOut`](crate::handlers::FanOut)
+    #[error(transparent)]
+    Shared(std::sync::Arc<Error>),
+    #[error("failed to subscribe to {event:?}: {source}")]
+    SubscriptionFailed {
+        event: crate::EventKind,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+/// The reasons a [`JoinFlow`](crate::activity::join::JoinFlow) can fail
+#[derive(thiserror::Error, Debug)]
+pub enum JoinError {
+    #[error("the join secret was not received in the allotted time")]
+    TimedOut,
+    #[error("Discord rejected the join: {0}")]
+    Rejected(#[source] Error),
+    #[error("the connection to Discord was lost: {0}")]
+    Disconnected(String),
+    #[error("the join secret could not be decoded: {0}")]
+    InvalidSecret(String),
+}
+
+/// The reasons a [`Template`](crate::activity::template::Template) can fail to
+/// be parsed or rendered
+#[derive(thiserror::Error, Debug)]
+pub enum TemplateError {
+    #[error("placeholder starting at byte {offset} is not closed")]
+    UnclosedPlaceholder { offset: usize },
+    #[error("placeholder at byte {offset} has an invalid name")]
+    InvalidPlaceholder { offset: usize },
+    #[error("unmatched '}}' at byte {offset}, use '}}}}' for a literal brace")]
+    UnmatchedBrace { offset: usize },
+    #[error("no variable was provided for placeholder '{0}'")]
+    MissingVariable(String),
+    #[error("no template exists for key '{0}'")]
+    UnknownKey(String),
+}
+
+/// The reasons an [`InviteCode`](crate::overlay::InviteCode) can fail to be
+/// parsed
+#[derive(thiserror::Error, Debug)]
+pub enum InviteCodeError {
+    #[error("the invite code is empty")]
+    Empty,
+    #[error("the invite code is {0} characters long, which is longer than the maximum of 32")]
+    TooLong(usize),
+    #[error("invalid character '{ch}' at byte {offset} in invite code")]
+    InvalidCharacter { ch: char, offset: usize },
+    #[error("'{0}' is not a Discord invite host")]
+    UnknownHost(String),
+    #[error("the url is not a Discord invite url")]
+    InvalidUrl,
 }
 
 impl<T> From<crossbeam_channel::TrySendError<T>> for Error {
@@ -79,6 +148,26 @@ impl Error {
     pub(crate) fn io(action: &'static str, error: std::io::Error) -> Self {
         Self::Io { action, error }
     }
+
+    /// The error itself, or the error that is shared if this is an
+    /// [`Error::Shared`]
+    pub fn unshared(&self) -> &Self {
+        match self {
+            Self::Shared(err) => err.unshared(),
+            err => err,
+        }
+    }
+
+    /// Moves the error into an [`Error::Shared`], returning a copy of it
+    pub(crate) fn share(&mut self) -> Self {
+        let shared = match std::mem::replace(self, Self::NoConnection) {
+            Self::Shared(shared) => shared,
+            err => std::sync::Arc::new(err),
+        };
+
+        *self = Self::Shared(shared.clone());
+        Self::Shared(shared)
+    }
 }
 
 /// An error related to the actual use of the Discord API.
@@ -120,6 +209,8 @@ pub enum DiscordApiErr {
     InvalidLobbySecret,
     #[error("invalid command: {reason}")]
     InvalidCommand { reason: String },
+    #[error("the requested user does not exist")]
+    UnknownUser,
 }
 
 impl<'stack> From<Option<crate::types::ErrorPayloadStack<'stack>>> for DiscordApiErr {
@@ -144,9 +235,14 @@ impl<'stack> From<Option<crate::types::ErrorPayloadStack<'stack>>> for DiscordAp
                     Some(inner) => match inner {
                         1000 => to_known("Unknown Error", Self::Unknown),
                         1003 => to_known("protocol error", Self::MalformedCommand),
-                        4000 => Self::InvalidCommand {
-                            reason: message
-                                .map_or_else(|| "unknown problem".to_owned(), |s| s.into_owned()),
+                        4000 => match message.as_deref() {
+                            Some("Unknown User") => Self::UnknownUser,
+                            _ => Self::InvalidCommand {
+                                reason: message.map_or_else(
+                                    || "unknown problem".to_owned(),
+                                    |s| s.into_owned(),
+                                ),
+                            },
                         },
                         4002 => match message.as_deref() {
                             Some(msg) if msg.starts_with("Invalid command: ") => {
@@ -162,6 +258,8 @@ impl<'stack> From<Option<crate::types::ErrorPayloadStack<'stack>>> for DiscordAp
                                 message: message.map(|s| s.into_owned()),
                             },
                         },
+                        // The HTTP API's error code for an unknown user
+                        10013 => Self::UnknownUser,
                         _ => Self::Generic {
                             code,
                             message: message.map(|s| s.into_owned()),
@@ -3,7 +3,7 @@ pub mod wheel;
 
 use crate::{
     io,
-    proto::{self, CommandKind, Event, EventKind, Rpc},
+    proto::{self, CommandKind, Event, EventKind},
     types::ErrorPayloadStack,
     Error,
 };
@@ -16,18 +16,60 @@ pub enum DiscordMsg {
     Error(Error),
 }
 
+impl DiscordMsg {
+    /// Copies the message for another handler, moving any error it contains
+    /// into an [`Error::Shared`]
+    pub(crate) fn share(&mut self) -> Self {
+        match self {
+            Self::Event(event) => Self::Event(event.share()),
+            Self::Error(err) => Self::Error(err.share()),
+        }
+    }
+}
+
 #[async_trait::async_trait]
 pub trait DiscordHandler: Send + Sync {
     /// Method called when an [`Event`] or [`Error`] is received from Discord
     async fn on_message(&self, msg: DiscordMsg);
 }
 
+/// An alternative to [`DiscordHandler`] with a method per event, similarly to
+/// the `EventHandler` in the official SDK. Every method does nothing by
+/// default, so only the events of interest need to be handled.
+///
+/// Use [`handlers::EventAdapter`] to turn it into a [`DiscordHandler`], or
+/// [`handlers::Callbacks`] to create one from closures.
+#[async_trait::async_trait]
+pub trait EventHandler: Send + Sync {
+    /// See [`Event::Ready`]
+    async fn on_ready(&self, _ready: crate::user::events::ConnectEvent) {}
+    /// See [`Event::Disconnected`]
+    async fn on_disconnect(&self, _reason: Error) {}
+    /// See [`Event::ActivityJoin`]
+    async fn on_activity_join(&self, _join: crate::activity::events::SecretEvent) {}
+    /// See [`Event::ActivityJoinRequest`]
+    async fn on_join_request(&self, _request: crate::activity::events::JoinRequestEvent) {}
+    /// See [`Event::ActivityInvite`]
+    async fn on_invite(&self, _invite: crate::activity::events::InviteEvent) {}
+    /// See [`Event::OverlayUpdate`]
+    async fn on_overlay_update(&self, _update: crate::overlay::events::UpdateEvent) {}
+    /// See [`Event::RelationshipUpdate`]
+    async fn on_relationship_update(
+        &self,
+        _relationship: std::sync::Arc<crate::relations::Relationship>,
+    ) {
+    }
+    /// Called when an [`Error`] is received that is not the response to an RPC
+    async fn on_error(&self, _error: Error) {}
+    /// Called for every [`Event`] that doesn't have its own method
+    async fn on_event(&self, _event: Event) {}
+}
+
 /// Creates a task which receives raw frame buffers and deserializes them, and either
 /// notifying the awaiting oneshot for a command response, or in the case of events,
 /// broadcasting the event to
 pub(crate) fn handler_task(
     handler: Box<dyn DiscordHandler>,
-    subscriptions: crate::Subscriptions,
     stx: cc::Sender<Option<Vec<u8>>>,
     mut rrx: tokio::sync::mpsc::Receiver<io::IoMsg>,
     state: crate::State,
@@ -48,7 +90,9 @@ pub(crate) fn handler_task(
         let (user_tx, mut user_rx) = tokio::sync::mpsc::unbounded_channel();
         let user_task = tokio::task::spawn(async move {
             while let Some(msg) = user_rx.recv().await {
-                handler.on_message(msg).await;
+                // Catch panics so that a misbehaving handler doesn't prevent
+                // every future message from being handled
+                handle_catching_panics(&*handler, msg).await;
             }
         });
 
@@ -60,9 +104,17 @@ pub(crate) fn handler_task(
             };
         }
 
+        // The user from the most recent connection, kept across disconnects so
+        // that we can detect when a different user is logged in after we
+        // reconnect
+        let mut last_user: Option<crate::user::User> = None;
+
         while let Some(io_msg) = rrx.recv().await {
             let msg = match io_msg {
                 io::IoMsg::Disconnected(err) => {
+                    state.session.send_replace(None);
+                    state.subscription_status.lock().clear();
+                    state.overlay.send_modify(|overlay| overlay.update(None));
                     user_send!(DiscordMsg::Event(Event::Disconnected { reason: err }));
                     continue;
                 }
@@ -71,53 +123,89 @@ pub(crate) fn handler_task(
 
             match msg {
                 Msg::Event(event) => {
+                    match &event {
+                        Event::Ready(ready) => {
+                            if let Some(old) = last_user
+                                .replace(ready.user.clone())
+                                .filter(|old| old.id != ready.user.id)
+                            {
+                                tracing::info!(
+                                    old = %old,
+                                    new = %ready.user,
+                                    "the logged in user changed"
+                                );
+
+                                user_send!(DiscordMsg::Event(Event::AccountChanged {
+                                    old,
+                                    new: ready.user.clone(),
+                                }));
+                            }
+
+                            state.session.send_replace(Some(crate::Session {
+                                user: ready.user.clone(),
+                                config: ready.config.clone(),
+                            }));
+                        }
+                        Event::CurrentUserUpdate(update) => {
+                            last_user = Some(update.user.clone());
+                            state.session.send_modify(|session| {
+                                if let Some(session) = session {
+                                    session.user = update.user.clone();
+                                }
+                            });
+                        }
+                        Event::OverlayUpdate(update) => {
+                            state.overlay.send_modify(|overlay| {
+                                overlay.update(Some(crate::wheel::OverlayState {
+                                    enabled: update.enabled,
+                                    visible: update.visible,
+                                }));
+                            });
+                        }
+                        _ => {}
+                    }
+
+                    state.events.publish(&event);
+
                     if let Event::Ready { .. } = &event {
-                        // Spawn a task that subscribes to all of the events
-                        // that the caller was interested in when we've finished
-                        // the handshake with Discord
-                        subscribe_task(subscriptions, stx.clone());
+                        // Subscribe to all of the events that the caller is
+                        // interested in now that we've finished the handshake
+                        // with Discord
+                        subscribe_task(&state, &stx, user_tx.clone());
                     }
 
                     user_send!(DiscordMsg::Event(event));
                 }
-                Msg::Command { command, kind } => {
-                    // Some commands can also be turned into events for consistency
-                    if let crate::proto::Command::Subscribe { evt } = &command.inner {
-                        tracing::debug!(event = ?evt, "subscription succeeded");
-                        continue;
-                    }
-
-                    match pop_nonce(command.nonce) {
-                        Some(ni) => {
-                            if ni
-                                .tx
-                                .send(if ni.cmd == kind {
-                                    Ok(command.inner)
-                                } else {
-                                    Err(Error::Discord(crate::DiscordErr::MismatchedResponse {
-                                        expected: ni.cmd,
-                                        actual: kind,
-                                        nonce: command.nonce,
-                                    }))
-                                })
-                                .is_err()
-                            {
-                                tracing::warn!(
-                                    cmd = ?kind,
-                                    nonce = command.nonce,
-                                    "command response dropped as receiver was closed",
-                                );
-                            }
-                        }
-                        None => {
+                Msg::Command { command, kind } => match pop_nonce(command.nonce) {
+                    Some(ni) => {
+                        if ni
+                            .tx
+                            .send(if ni.cmd == kind {
+                                Ok(command.inner)
+                            } else {
+                                Err(Error::Discord(crate::DiscordErr::MismatchedResponse {
+                                    expected: ni.cmd,
+                                    actual: kind,
+                                    nonce: command.nonce,
+                                }))
+                            })
+                            .is_err()
+                        {
                             tracing::warn!(
-                                cmd = ?command.inner,
+                                cmd = ?kind,
                                 nonce = command.nonce,
-                                "received a command response with an unknown nonce",
+                                "command response dropped as receiver was closed",
                             );
                         }
                     }
-                }
+                    None => {
+                        tracing::warn!(
+                            cmd = ?command.inner,
+                            nonce = command.nonce,
+                            "received a command response with an unknown nonce",
+                        );
+                    }
+                },
                 Msg::Error { nonce, error, .. } => match nonce {
                     Some(nonce) => match pop_nonce(nonce) {
                         Some(ni) => {
@@ -145,6 +233,64 @@ pub(crate) fn handler_task(
     })
 }
 
+/// Passes the message to the handler, catching any panic and reporting it to
+/// the handler as an [`Error::HandlerPanicked`]
+pub(crate) async fn handle_catching_panics<H>(handler: &H, msg: DiscordMsg)
+where
+    H: DiscordHandler + ?Sized,
+{
+    let name = msg.name();
+    let Err(panic) = CatchUnwind::new(handler.on_message(msg)).await else {
+        return;
+    };
+
+    tracing::error!(msg = name, panic = %panic, "handler panicked");
+
+    let report = DiscordMsg::Error(Error::HandlerPanicked { msg: name, panic });
+    if let Err(panic) = CatchUnwind::new(handler.on_message(report)).await {
+        tracing::error!(panic = %panic, "handler panicked while handling a panic report");
+    }
+}
+
+/// Future that catches a panic while polling the inner future, resolving to
+/// the panic message instead
+pub(crate) struct CatchUnwind<F> {
+    inner: std::pin::Pin<Box<F>>,
+}
+
+impl<F: std::future::Future> CatchUnwind<F> {
+    pub(crate) fn new(inner: F) -> Self {
+        Self {
+            inner: Box::pin(inner),
+        }
+    }
+}
+
+impl<F: std::future::Future> std::future::Future for CatchUnwind<F> {
+    type Output = Result<F::Output, String>;
+
+    fn poll(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        let inner = self.inner.as_mut();
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| inner.poll(cx))) {
+            Ok(poll) => poll.map(Ok),
+            Err(payload) => {
+                let msg = if let Some(msg) = payload.downcast_ref::<&'static str>() {
+                    (*msg).to_owned()
+                } else if let Some(msg) = payload.downcast_ref::<String>() {
+                    msg.clone()
+                } else {
+                    "unknown panic".to_owned()
+                };
+
+                std::task::Poll::Ready(Err(msg))
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(crate) enum Msg {
     Command {
@@ -247,85 +393,32 @@ fn process_frame(data_buf: Vec<u8>) -> Msg {
     }
 }
 
-fn subscribe_task(subs: crate::Subscriptions, stx: cc::Sender<Option<Vec<u8>>>) {
-    tokio::task::spawn(async move {
-        // Assume a max of 64KiB write size and just write all of the
-        // subscriptions into a single buffer rather than n
-        let mut buffer = Vec::with_capacity(1024);
-        let mut nonce = 1usize;
-
-        let mut push = |kind: EventKind| {
-            #[cfg(target_pointer_width = "32")]
-            let nunce = 0x10000000 | nonce;
-            #[cfg(target_pointer_width = "64")]
-            let nunce = 0x1000000000000000 | nonce;
-
-            let _ = io::serialize_message(
-                io::OpCode::Frame,
-                &Rpc::<()> {
-                    cmd: CommandKind::Subscribe,
-                    evt: Some(kind),
-                    nonce: nunce.to_string(),
-                    args: None,
-                },
-                &mut buffer,
-            );
-
-            nonce += 1;
-        };
-
-        let activity = if subs.contains(crate::Subscriptions::ACTIVITY) {
-            [
-                EventKind::ActivityInvite,
-                EventKind::ActivityJoin,
-                EventKind::ActivityJoinRequest,
-                EventKind::ActivitySpectate,
-            ]
-            .iter()
-        } else {
-            [].iter()
-        };
-
-        let user = if subs.contains(crate::Subscriptions::USER) {
-            [EventKind::CurrentUserUpdate].iter()
-        } else {
-            [].iter()
-        };
-
-        let relations = if subs.contains(crate::Subscriptions::RELATIONSHIPS) {
-            [EventKind::RelationshipUpdate].iter()
-        } else {
-            [].iter()
-        };
-
-        activity.chain(user).chain(relations).for_each(|kind| {
-            push(*kind);
-        });
-
-        // Unlike EVERY other event, subscribing to OVERLAY_UPDATE requires
-        // an argument... :facepalm:
-        if subs.contains(crate::Subscriptions::OVERLAY) {
-            #[cfg(target_pointer_width = "32")]
-            let nunce = 0x10000000 | nonce;
-            #[cfg(target_pointer_width = "64")]
-            let nunce = 0x1000000000000000 | nonce;
-
-            let _ = io::serialize_message(
-                io::OpCode::Frame,
-                &Rpc {
-                    cmd: CommandKind::Subscribe,
-                    evt: Some(EventKind::OverlayUpdate),
-                    nonce: nunce.to_string(),
-                    args: Some(crate::overlay::OverlayPidArgs::new()),
-                },
-                &mut buffer,
-            );
-
-            //nonce += 1;
+/// Subscribes to all of the events the caller is interested in, reporting any
+/// failures to the user's handler
+fn subscribe_task(
+    state: &crate::State,
+    stx: &cc::Sender<Option<Vec<u8>>>,
+    user_tx: tokio::sync::mpsc::UnboundedSender<DiscordMsg>,
+) {
+    let subs = *state.subscriptions.lock();
+
+    let pending = match state.send_subscriptions(stx, CommandKind::Subscribe, subs) {
+        Ok(pending) => pending,
+        Err(err) => {
+            tracing::warn!(error = %err, "unable to send subscription RPCs to I/O task");
+            return;
         }
+    };
 
-        if stx.send(Some(buffer)).is_err() {
-            tracing::warn!("unable to send subscription RPCs to I/O task");
+    tokio::task::spawn(async move {
+        for sub in pending {
+            if let Err(err) = sub.wait().await {
+                tracing::warn!(error = %err, "subscription failed");
+
+                if user_tx.send(DiscordMsg::Error(err)).is_err() {
+                    tracing::warn!("user handler task has been dropped");
+                }
+            }
         }
     });
 }
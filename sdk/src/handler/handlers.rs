@@ -1,5 +1,13 @@
-use super::{DiscordHandler, DiscordMsg};
+use super::{DiscordHandler, DiscordMsg, EventHandler};
+use crate::{
+    activity::events::{InviteEvent, JoinRequestEvent, SecretEvent},
+    overlay::events::UpdateEvent,
+    relations::Relationship,
+    user::events::ConnectEvent,
+    Error, Event,
+};
 use async_trait::async_trait;
+use std::{future::Future, pin::Pin, sync::Arc};
 
 /// Prints events at [`tracing::Level::DEBUG`] and errors at [`tracing::Level::WARN`]
 pub struct Printer;
@@ -41,3 +49,649 @@ impl DiscordHandler for Forwarder {
         }
     }
 }
+
+/// Adapts an [`EventHandler`] into a [`DiscordHandler`], dispatching each
+/// message to the corresponding method
+///
+/// ```no_run
+/// use discord_sdk as ds;
+///
+/// struct Handler;
+///
+/// #[ds::async_trait]
+/// impl ds::EventHandler for Handler {
+///     async fn on_activity_join(&self, join: ds::activity::events::SecretEvent) {
+///         println!("joining {}", join.secret);
+///     }
+/// }
+///
+/// let handler = ds::handlers::EventAdapter::new(Handler);
+/// let discord = ds::Discord::new(ds::DiscordApp::PlainId(1), ds::Subscriptions::ALL, Box::new(handler)).unwrap();
+/// ```
+pub struct EventAdapter<H> {
+    inner: H,
+}
+
+impl<H: EventHandler> EventAdapter<H> {
+    pub fn new(inner: H) -> Self {
+        Self { inner }
+    }
+
+    /// The wrapped [`EventHandler`]
+    pub fn inner(&self) -> &H {
+        &self.inner
+    }
+}
+
+#[async_trait]
+impl<H: EventHandler> DiscordHandler for EventAdapter<H> {
+    async fn on_message(&self, msg: DiscordMsg) {
+        let h = &self.inner;
+
+        match msg {
+            DiscordMsg::Error(err) => h.on_error(err).await,
+            DiscordMsg::Event(event) => match event {
+                Event::Ready(ready) => h.on_ready(ready).await,
+                Event::Disconnected { reason } => h.on_disconnect(reason).await,
+                Event::ActivityJoin(join) => h.on_activity_join(join).await,
+                Event::ActivityJoinRequest(request) => h.on_join_request(request).await,
+                Event::ActivityInvite(invite) => h.on_invite(invite).await,
+                Event::OverlayUpdate(update) => h.on_overlay_update(update).await,
+                Event::RelationshipUpdate(rel) => h.on_relationship_update(rel).await,
+                other => h.on_event(other).await,
+            },
+        }
+    }
+}
+
+type Callback<T> = Box<dyn Fn(T) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
+
+fn callback<T, F, Fut>(f: F) -> Option<Callback<T>>
+where
+    F: Fn(T) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    Some(Box::new(move |arg| Box::pin(f(arg))))
+}
+
+macro_rules! callbacks {
+    ($($(#[$meta:meta])* $name:ident($arg:ty);)+) => {
+        /// Builds an [`EventHandler`] from closures, any event without a
+        /// closure is ignored
+        ///
+        /// ```no_run
+        /// use discord_sdk as ds;
+        ///
+        /// let handler = ds::handlers::Callbacks::new()
+        ///     .on_ready(|ready| async move { println!("connected as {}", ready.user) })
+        ///     .on_error(|err| async move { eprintln!("{err}") })
+        ///     .boxed();
+        /// let discord = ds::Discord::new(ds::DiscordApp::PlainId(1), ds::Subscriptions::ALL, handler).unwrap();
+        /// ```
+        #[derive(Default)]
+        pub struct Callbacks {
+            $($name: Option<Callback<$arg>>,)+
+        }
+
+        impl Callbacks {
+            pub fn new() -> Self {
+                Self::default()
+            }
+
+            /// Wraps the callbacks in an [`EventAdapter`] so they can be passed
+            /// to [`Discord::new`](crate::Discord::new)
+            pub fn boxed(self) -> Box<dyn DiscordHandler> {
+                Box::new(EventAdapter::new(self))
+            }
+
+            $(
+                $(#[$meta])*
+                pub fn $name<F, Fut>(mut self, f: F) -> Self
+                where
+                    F: Fn($arg) -> Fut + Send + Sync + 'static,
+                    Fut: Future<Output = ()> + Send + 'static,
+                {
+                    self.$name = callback(f);
+                    self
+                }
+            )+
+        }
+
+        #[async_trait]
+        impl EventHandler for Callbacks {
+            $(
+                async fn $name(&self, arg: $arg) {
+                    if let Some(cb) = &self.$name {
+                        cb(arg).await;
+                    }
+                }
+            )+
+        }
+    };
+}
+
+callbacks! {
+    /// Sets the closure for [`EventHandler::on_ready`]
+    on_ready(ConnectEvent);
+    /// Sets the closure for [`EventHandler::on_disconnect`]
+    on_disconnect(Error);
+    /// Sets the closure for [`EventHandler::on_activity_join`]
+    on_activity_join(SecretEvent);
+    /// Sets the closure for [`EventHandler::on_join_request`]
+    on_join_request(JoinRequestEvent);
+    /// Sets the closure for [`EventHandler::on_invite`]
+    on_invite(InviteEvent);
+    /// Sets the closure for [`EventHandler::on_overlay_update`]
+    on_overlay_update(UpdateEvent);
+    /// Sets the closure for [`EventHandler::on_relationship_update`]
+    on_relationship_update(Arc<Relationship>);
+    /// Sets the closure for [`EventHandler::on_error`]
+    on_error(Error);
+    /// Sets the closure for [`EventHandler::on_event`]
+    on_event(Event);
+}
+
+/// The broad class of a [`DiscordMsg`], see [`DiscordMsg::class`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MsgClass {
+    /// A [`DiscordMsg::Error`], or an [`Event::Error`]
+    Error,
+    /// Connection and current user events
+    User,
+    /// Activity events
+    Activity,
+    /// Overlay events
+    Overlay,
+    /// Relationship events
+    Relationships,
+}
+
+impl DiscordMsg {
+    /// The class of the message, eg. for [`HandlerExt::filter`]
+    pub fn class(&self) -> MsgClass {
+        match self {
+            Self::Error(_) | Self::Event(Event::Error(_)) => MsgClass::Error,
+            Self::Event(event) => match event {
+                Event::Ready(_)
+                | Event::Disconnected { .. }
+                | Event::AccountChanged { .. }
+                | Event::CurrentUserUpdate(_) => MsgClass::User,
+                Event::ActivityJoin(_)
+                | Event::ActivitySpectate(_)
+                | Event::ActivityJoinRequest(_)
+                | Event::ActivityInvite(_) => MsgClass::Activity,
+                Event::OverlayUpdate(_) => MsgClass::Overlay,
+                Event::RelationshipUpdate(_) => MsgClass::Relationships,
+                Event::Error(_) => MsgClass::Error,
+            },
+        }
+    }
+
+    /// A short name for the message, eg. `ACTIVITY_JOIN`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Error(_) => "ERROR",
+            Self::Event(event) => match event {
+                Event::Error(_) => "EVENT_ERROR",
+                Event::Ready(_) => "READY",
+                Event::Disconnected { .. } => "DISCONNECTED",
+                Event::AccountChanged { .. } => "ACCOUNT_CHANGED",
+                Event::CurrentUserUpdate(_) => "CURRENT_USER_UPDATE",
+                Event::ActivityJoin(_) => "ACTIVITY_JOIN",
+                Event::ActivitySpectate(_) => "ACTIVITY_SPECTATE",
+                Event::ActivityJoinRequest(_) => "ACTIVITY_JOIN_REQUEST",
+                Event::ActivityInvite(_) => "ACTIVITY_INVITE",
+                Event::OverlayUpdate(_) => "OVERLAY_UPDATE",
+                Event::RelationshipUpdate(_) => "RELATIONSHIP_UPDATE",
+            },
+        }
+    }
+}
+
+/// Sends every message to each of several handlers in turn, eg. to use the
+/// [`Wheel`](crate::wheel::Wheel) alongside a game's own [`EventHandler`]
+///
+/// Every handler receives its own copy of the message. Since errors can't be
+/// copied, every handler receives them as an [`Error::Shared`], use
+/// [`Error::unshared`] to get the original error.
+///
+/// A panic in one handler doesn't prevent the other handlers from receiving
+/// the message, and is reported to them as an [`Error::HandlerPanicked`] once
+/// they have.
+///
+/// ```no_run
+/// use discord_sdk as ds;
+/// use ds::handlers::HandlerExt;
+///
+/// let (wheel, wheel_handler) = ds::wheel::Wheel::new(Box::new(|err| {
+///     eprintln!("{err}");
+/// }));
+///
+/// let game = ds::handlers::Callbacks::new()
+///     .on_activity_join(|join| async move { println!("joining {}", join.secret) });
+///
+/// let handler = ds::handlers::FanOut::new()
+///     .with(wheel_handler)
+///     .with(ds::handlers::EventAdapter::new(game).traced())
+///     .with(ds::handlers::Printer);
+/// let discord = ds::Discord::new(ds::DiscordApp::PlainId(1), ds::Subscriptions::ALL, Box::new(handler)).unwrap();
+/// ```
+#[derive(Default)]
+pub struct FanOut {
+    handlers: Vec<Box<dyn DiscordHandler>>,
+}
+
+impl FanOut {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a handler, handlers receive messages in the order they were added
+    pub fn with(mut self, handler: impl DiscordHandler + 'static) -> Self {
+        self.handlers.push(Box::new(handler));
+        self
+    }
+
+    /// Adds an already boxed handler
+    pub fn with_boxed(mut self, handler: Box<dyn DiscordHandler>) -> Self {
+        self.handlers.push(handler);
+        self
+    }
+}
+
+#[async_trait]
+impl DiscordHandler for FanOut {
+    async fn on_message(&self, mut msg: DiscordMsg) {
+        if let [handler] = self.handlers.as_slice() {
+            handler.on_message(msg).await;
+            return;
+        }
+
+        let name = msg.name();
+        let mut panics = Vec::new();
+
+        for (i, handler) in self.handlers.iter().enumerate() {
+            if let Err(panic) = super::CatchUnwind::new(handler.on_message(msg.share())).await {
+                tracing::error!(msg = name, panic = %panic, "handler panicked");
+                panics.push((i, panic));
+            }
+        }
+
+        for (panicked, panic) in panics {
+            for (i, handler) in self.handlers.iter().enumerate() {
+                if i == panicked {
+                    continue;
+                }
+
+                let report = DiscordMsg::Error(Error::HandlerPanicked {
+                    msg: name,
+                    panic: panic.clone(),
+                });
+                if let Err(panic) = super::CatchUnwind::new(handler.on_message(report)).await {
+                    tracing::error!(panic = %panic, "handler panicked while handling a panic report");
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl DiscordHandler for Box<dyn DiscordHandler> {
+    async fn on_message(&self, msg: DiscordMsg) {
+        (**self).on_message(msg).await;
+    }
+}
+
+/// Combinators for any [`DiscordHandler`]
+pub trait HandlerExt: DiscordHandler + Sized {
+    /// Only passes messages that match the predicate to the handler
+    ///
+    /// ```
+    /// use discord_sdk::handlers::{HandlerExt, MsgClass, Printer};
+    ///
+    /// let activity_only = Printer.filter(|msg| msg.class() == MsgClass::Activity);
+    /// ```
+    fn filter<F>(self, pred: F) -> Filter<Self, F>
+    where
+        F: Fn(&DiscordMsg) -> bool + Send + Sync,
+    {
+        Filter { inner: self, pred }
+    }
+
+    /// Transforms messages before they are passed to the handler, messages
+    /// mapped to `None` are dropped
+    fn map<F>(self, map: F) -> Map<Self, F>
+    where
+        F: Fn(DiscordMsg) -> Option<DiscordMsg> + Send + Sync,
+    {
+        Map { inner: self, map }
+    }
+
+    /// Calls the closure with each message before it is passed to the
+    /// handler, eg. to record metrics
+    fn inspect<F>(self, inspect: F) -> Inspect<Self, F>
+    where
+        F: Fn(&DiscordMsg) + Send + Sync,
+    {
+        Inspect {
+            inner: self,
+            inspect,
+        }
+    }
+
+    /// Handles each message in a [`tracing`] span with the message's class
+    /// and name as fields, logging how long the handler took, and any errors
+    fn traced(self) -> Traced<Self> {
+        Traced { inner: self }
+    }
+
+    /// Catches any panic in the handler rather than unwinding into the
+    /// caller. The panic is logged with [`tracing`], and reported to the
+    /// handler as an [`Error::HandlerPanicked`].
+    fn catch_panics(self) -> CatchPanics<Self> {
+        CatchPanics { inner: self }
+    }
+}
+
+impl<H: DiscordHandler> HandlerExt for H {}
+
+/// See [`HandlerExt::filter`]
+pub struct Filter<H, F> {
+    inner: H,
+    pred: F,
+}
+
+#[async_trait]
+impl<H, F> DiscordHandler for Filter<H, F>
+where
+    H: DiscordHandler,
+    F: Fn(&DiscordMsg) -> bool + Send + Sync,
+{
+    async fn on_message(&self, msg: DiscordMsg) {
+        if (self.pred)(&msg) {
+            self.inner.on_message(msg).await;
+        }
+    }
+}
+
+/// See [`HandlerExt::map`]
+pub struct Map<H, F> {
+    inner: H,
+    map: F,
+}
+
+#[async_trait]
+impl<H, F> DiscordHandler for Map<H, F>
+where
+    H: DiscordHandler,
+    F: Fn(DiscordMsg) -> Option<DiscordMsg> + Send + Sync,
+{
+    async fn on_message(&self, msg: DiscordMsg) {
+        if let Some(msg) = (self.map)(msg) {
+            self.inner.on_message(msg).await;
+        }
+    }
+}
+
+/// See [`HandlerExt::inspect`]
+pub struct Inspect<H, F> {
+    inner: H,
+    inspect: F,
+}
+
+#[async_trait]
+impl<H, F> DiscordHandler for Inspect<H, F>
+where
+    H: DiscordHandler,
+    F: Fn(&DiscordMsg) + Send + Sync,
+{
+    async fn on_message(&self, msg: DiscordMsg) {
+        (self.inspect)(&msg);
+        self.inner.on_message(msg).await;
+    }
+}
+
+/// See [`HandlerExt::traced`]
+pub struct Traced<H> {
+    inner: H,
+}
+
+#[async_trait]
+impl<H: DiscordHandler> DiscordHandler for Traced<H> {
+    async fn on_message(&self, msg: DiscordMsg) {
+        use tracing::Instrument;
+
+        let span = tracing::debug_span!("discord_msg", class = ?msg.class(), name = msg.name());
+
+        if let DiscordMsg::Error(err) = &msg {
+            span.in_scope(|| tracing::warn!(error = %err, "received error"));
+        }
+
+        async {
+            let start = std::time::Instant::now();
+            self.inner.on_message(msg).await;
+            tracing::debug!(elapsed = ?start.elapsed(), "handled message");
+        }
+        .instrument(span)
+        .await;
+    }
+}
+
+/// See [`HandlerExt::catch_panics`]
+pub struct CatchPanics<H> {
+    inner: H,
+}
+
+#[async_trait]
+impl<H: DiscordHandler> DiscordHandler for CatchPanics<H> {
+    async fn on_message(&self, msg: DiscordMsg) {
+        super::handle_catching_panics(&self.inner, msg).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn dispatches() {
+        let joins = Arc::new(AtomicUsize::new(0));
+        let others = Arc::new(AtomicUsize::new(0));
+
+        let handler = {
+            let (joins, others) = (joins.clone(), others.clone());
+            Callbacks::new()
+                .on_activity_join(move |join| {
+                    assert_eq!(join.secret, "secret");
+                    joins.fetch_add(1, Ordering::Relaxed);
+                    async {}
+                })
+                .on_event(move |_event| {
+                    others.fetch_add(1, Ordering::Relaxed);
+                    async {}
+                })
+                .boxed()
+        };
+
+        let secret = || SecretEvent {
+            secret: "secret".to_owned(),
+        };
+
+        handler
+            .on_message(DiscordMsg::Event(Event::ActivityJoin(secret())))
+            .await;
+        handler
+            .on_message(DiscordMsg::Event(Event::ActivitySpectate(secret())))
+            .await;
+        // No callback, so it's ignored
+        handler
+            .on_message(DiscordMsg::Error(Error::NoConnection))
+            .await;
+
+        assert_eq!(joins.load(Ordering::Relaxed), 1);
+        assert_eq!(others.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn combinators() {
+        let seen = Arc::new(AtomicUsize::new(0));
+        let activity = Arc::new(AtomicUsize::new(0));
+
+        let (forwarder, mut rx) = Forwarder::new();
+
+        let join = || {
+            DiscordMsg::Event(Event::ActivityJoin(SecretEvent {
+                secret: "secret".to_owned(),
+            }))
+        };
+
+        let handler = {
+            let seen = seen.clone();
+            forwarder.inspect(move |_msg| {
+                seen.fetch_add(1, Ordering::Relaxed);
+            })
+        };
+
+        handler.on_message(join()).await;
+        handler
+            .on_message(DiscordMsg::Error(Error::NoConnection))
+            .await;
+
+        assert_eq!(seen.load(Ordering::Relaxed), 2);
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            DiscordMsg::Event(Event::ActivityJoin(_))
+        ));
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            DiscordMsg::Error(Error::NoConnection)
+        ));
+
+        let handler = {
+            let activity = activity.clone();
+            Callbacks::new()
+                .on_event(move |_event| {
+                    activity.fetch_add(1, Ordering::Relaxed);
+                    async {}
+                })
+                .boxed()
+                .filter(|msg| msg.class() == MsgClass::Activity)
+                .map(|msg| match msg {
+                    DiscordMsg::Event(Event::ActivityJoin(join)) => {
+                        Some(DiscordMsg::Event(Event::ActivitySpectate(join)))
+                    }
+                    other => Some(other),
+                })
+                .traced()
+        };
+
+        handler.on_message(join()).await;
+        handler
+            .on_message(DiscordMsg::Error(Error::NoConnection))
+            .await;
+
+        // The join was mapped to a spectate and the error was filtered out
+        assert_eq!(activity.load(Ordering::Relaxed), 1);
+
+        // Panics are caught rather than unwinding into the caller, and are
+        // reported to the handler
+        let reported = Arc::new(AtomicUsize::new(0));
+        {
+            let reported = reported.clone();
+            Callbacks::new()
+                .on_activity_join(|_join| async { panic!("oh no") })
+                .on_error(move |err| {
+                    assert!(matches!(
+                        err,
+                        Error::HandlerPanicked { msg: "ACTIVITY_JOIN", panic } if panic == "oh no"
+                    ));
+                    reported.fetch_add(1, Ordering::Relaxed);
+                    async {}
+                })
+                .boxed()
+                .catch_panics()
+                .on_message(join())
+                .await;
+        }
+        assert_eq!(reported.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn fans_out() {
+        struct Game {
+            joins: Arc<AtomicUsize>,
+        }
+
+        #[async_trait]
+        impl EventHandler for Game {
+            async fn on_activity_join(&self, join: SecretEvent) {
+                assert_eq!(join.secret, "secret");
+                self.joins.fetch_add(1, Ordering::Relaxed);
+            }
+
+            async fn on_disconnect(&self, reason: Error) {
+                assert!(matches!(reason.unshared(), Error::NoConnection));
+            }
+        }
+
+        let joins = Arc::new(AtomicUsize::new(0));
+        let (wheel, wheel_handler) = crate::wheel::Wheel::new(Box::new(|_err| {}));
+        let mut activity = wheel.activity().0;
+        let mut user = wheel.user().0;
+
+        let handler = FanOut::new()
+            .with(wheel_handler)
+            .with(EventAdapter::new(Game {
+                joins: joins.clone(),
+            }));
+
+        handler
+            .on_message(DiscordMsg::Event(Event::ActivityJoin(SecretEvent {
+                secret: "secret".to_owned(),
+            })))
+            .await;
+        handler
+            .on_message(DiscordMsg::Event(Event::Disconnected {
+                reason: Error::NoConnection,
+            }))
+            .await;
+
+        // A panic in one handler is reported to the others once they have
+        // received the message
+        let (forwarder, mut rx) = Forwarder::new();
+        FanOut::new()
+            .with(
+                Callbacks::new()
+                    .on_activity_join(|_join| async { panic!("oh no") })
+                    .boxed(),
+            )
+            .with(forwarder)
+            .on_message(DiscordMsg::Event(Event::ActivityJoin(SecretEvent {
+                secret: "secret".to_owned(),
+            })))
+            .await;
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            DiscordMsg::Event(Event::ActivityJoin(_))
+        ));
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            DiscordMsg::Error(Error::HandlerPanicked {
+                msg: "ACTIVITY_JOIN",
+                ..
+            })
+        ));
+
+        // Both the wheel and the game's handler received every message
+        assert!(matches!(
+            activity.recv().await.unwrap(),
+            crate::activity::events::ActivityEvent::Join(join) if join.secret == "secret"
+        ));
+        assert_eq!(joins.load(Ordering::Relaxed), 1);
+        assert!(matches!(
+            &*user.borrow_and_update(),
+            crate::wheel::UserState::Disconnected(err) if matches!(err.unshared(), Error::NoConnection)
+        ));
+    }
+}
@@ -1,26 +1,164 @@
 use crate::{
-    activity::events::ActivityEvent,
+    activity::events::{ActivityEvent, SecretEvent},
     handler::DiscordMsg,
     overlay::events::OverlayEvent,
     proto::event::ClassifiedEvent,
-    relations::events::RelationshipEvent,
+    relations::{
+        events::RelationshipEvent,
+        state::{RelationshipDiff, Relationships},
+        transitions::{self, PresenceTransitionEvent},
+        Relationship,
+    },
     user::{events::UserEvent, User},
 };
+use parking_lot::Mutex;
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tokio::sync::{broadcast, watch};
 
+/// Configuration for a [`Wheel`]
+#[derive(Clone, Debug)]
+pub struct WheelConfig {
+    /// The number of events each broadcast spoke can buffer before slow
+    /// receivers start lagging behind, a capacity of 0 is treated as 1
+    pub capacity: usize,
+    /// The maximum number of one-shot activity events, ie. joins, spectates,
+    /// invites and join requests, that are retained when they are received
+    /// while no one is subscribed to the [`ActivitySpoke`]. The oldest events
+    /// are dropped first. `0` disables retention.
+    pub unclaimed_limit: usize,
+    /// How long an unclaimed activity event is retained before it is dropped
+    pub unclaimed_ttl: Duration,
+}
+
+impl Default for WheelConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 10,
+            unclaimed_limit: 10,
+            unclaimed_ttl: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+/// Activity events that were received while no one was subscribed to the
+/// activity spoke, eg. when Discord launches the application to join another
+/// user's game
+struct Unclaimed {
+    events: VecDeque<(Instant, ActivityEvent)>,
+    limit: usize,
+    ttl: Duration,
+}
+
+impl Unclaimed {
+    fn new(config: &WheelConfig) -> Self {
+        Self {
+            events: VecDeque::new(),
+            limit: config.unclaimed_limit,
+            ttl: config.unclaimed_ttl,
+        }
+    }
+
+    /// Drops the events which have been retained for longer than the ttl
+    fn expire(&mut self) {
+        let ttl = self.ttl;
+        self.events.retain(|(received, _)| received.elapsed() < ttl);
+    }
+
+    fn retain(&mut self, event: ActivityEvent) {
+        self.expire();
+
+        if self.limit == 0 {
+            tracing::warn!(event = ?event, "Activity event was unobserved");
+            return;
+        }
+
+        while self.events.len() >= self.limit {
+            if let Some((_, dropped)) = self.events.pop_front() {
+                tracing::warn!(event = ?dropped, "Unclaimed activity event was dropped");
+            }
+        }
+
+        tracing::debug!(event = ?event, "Activity event was unobserved, retaining it");
+        self.events.push_back((Instant::now(), event));
+    }
+
+    fn take_all(&mut self) -> Vec<ActivityEvent> {
+        self.expire();
+        self.events.drain(..).map(|(_, event)| event).collect()
+    }
+
+    /// Takes the most recent join, dropping any older ones as they've been
+    /// superseded
+    fn take_join(&mut self) -> Option<SecretEvent> {
+        self.expire();
+
+        let mut join = None;
+        self.events.retain(|(_, event)| match event {
+            ActivityEvent::Join(secret) => {
+                if let Some(older) = join.replace(secret.clone()) {
+                    tracing::warn!(event = ?older, "Superseded unclaimed join was dropped");
+                }
+                false
+            }
+            _ => true,
+        });
+
+        join
+    }
+
+    /// Takes the join with the specified secret, leaving any other joins
+    fn take_join_matching(&mut self, secret: &str) -> Option<SecretEvent> {
+        self.expire();
+
+        let index = self.events.iter().position(
+            |(_, event)| matches!(event, ActivityEvent::Join(join) if join.secret == secret),
+        )?;
+
+        match self.events.remove(index) {
+            Some((_, ActivityEvent::Join(join))) => Some(join),
+            _ => None,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.events.clear();
+    }
+}
+
 /// An event wheel, with a different `spoke` per class of events
 pub struct Wheel {
     activity: broadcast::Sender<ActivityEvent>,
     relations: broadcast::Sender<RelationshipEvent>,
+    presence: broadcast::Sender<PresenceTransitionEvent>,
 
     user: watch::Receiver<UserState>,
     overlay: watch::Receiver<OverlayState>,
+
+    /// The relationship state maintained by the handler, used to derive
+    /// presence transitions
+    relationship_state: Arc<Relationships>,
+
+    /// Activity events that were received when no one was subscribed to the
+    /// activity spoke
+    unclaimed: Arc<Mutex<Unclaimed>>,
 }
 
 impl Wheel {
     pub fn new(error: Box<dyn OnError>) -> (Self, WheelHandler) {
-        let (activity_tx, _activity_rx) = broadcast::channel(10);
-        let (rl_tx, _rl_rx) = broadcast::channel(10);
+        Self::with_config(error, WheelConfig::default())
+    }
+
+    /// Creates a wheel with a non-default [`WheelConfig`]
+    pub fn with_config(error: Box<dyn OnError>, config: WheelConfig) -> (Self, WheelHandler) {
+        // Broadcast channels can't have a capacity of 0
+        let capacity = config.capacity.max(1);
+        let (activity_tx, _activity_rx) = broadcast::channel(capacity);
+        let (rl_tx, _rl_rx) = broadcast::channel(capacity);
+        let (presence_tx, _presence_rx) = broadcast::channel(capacity);
 
         let (user_tx, user_rx) =
             watch::channel(UserState::Disconnected(crate::Error::NoConnection));
@@ -29,18 +167,27 @@ impl Wheel {
             visible: crate::overlay::Visibility::Hidden,
         });
 
+        let unclaimed = Arc::new(Mutex::new(Unclaimed::new(&config)));
+        let relationship_state = Arc::new(Relationships::default());
+
         (
             Self {
                 activity: activity_tx.clone(),
                 relations: rl_tx.clone(),
+                presence: presence_tx.clone(),
                 user: user_rx,
                 overlay: overlay_rx,
+                relationship_state: relationship_state.clone(),
+                unclaimed: unclaimed.clone(),
             },
             WheelHandler {
                 activity: activity_tx,
                 relations: rl_tx,
+                presence: presence_tx,
                 user: user_tx,
                 overlay: overlay_tx,
+                relationship_state,
+                unclaimed,
                 error,
             },
         )
@@ -56,6 +203,20 @@ impl Wheel {
         RelationshipSpoke(self.relations.subscribe())
     }
 
+    /// Transitions in the presence of the current user's friends, eg. when a
+    /// friend comes online or starts playing a game.
+    ///
+    /// A friend whose previous presence is not known, eg. because they were
+    /// not a friend before, is treated as if they were offline without an
+    /// activity. Use [`Self::seed_relationships`] with the result of
+    /// [`Discord::get_relationships`](crate::Discord::get_relationships) so
+    /// that the first update of each existing friend only reports what
+    /// actually changed.
+    #[inline]
+    pub fn presence(&self) -> PresenceSpoke {
+        PresenceSpoke(self.presence.subscribe())
+    }
+
     #[inline]
     pub fn user(&self) -> UserSpoke {
         UserSpoke(self.user.clone())
@@ -65,10 +226,58 @@ impl Wheel {
     pub fn overlay(&self) -> OverlaySpoke {
         OverlaySpoke(self.overlay.clone())
     }
+
+    /// Takes the most recent join secret that was received while no one was
+    /// subscribed to the [`ActivitySpoke`], if there is one. Any older join
+    /// secrets are dropped.
+    #[inline]
+    pub fn take_unclaimed_join(&self) -> Option<SecretEvent> {
+        self.unclaimed.lock().take_join()
+    }
+
+    /// Takes the join secret matching `secret` that was received while no one
+    /// was subscribed to the [`ActivitySpoke`], if there is one. Unlike
+    /// [`Self::take_unclaimed_join`], any other join secrets are kept.
+    #[inline]
+    pub fn take_unclaimed_join_matching(&self, secret: &str) -> Option<SecretEvent> {
+        self.unclaimed.lock().take_join_matching(secret)
+    }
+
+    /// Takes all of the activity events that were received while no one was
+    /// subscribed to the [`ActivitySpoke`], oldest first, limited by the
+    /// [`WheelConfig`]. To avoid missing events, subscribe to the spoke before
+    /// taking the unclaimed events.
+    #[inline]
+    pub fn take_unclaimed(&self) -> Vec<ActivityEvent> {
+        self.unclaimed.lock().take_all()
+    }
+
+    /// Publishes an activity event on the activity spoke as if it had been
+    /// sent by Discord. The event is retained if no one is subscribed to the
+    /// spoke, just as it would be if Discord had sent it.
+    pub(crate) fn publish_activity(&self, event: ActivityEvent) {
+        if let Err(e) = self.activity.send(event) {
+            self.unclaimed.lock().retain(e.0);
+        }
+    }
+
+    /// The current user's relationships, kept up to date with every
+    /// [`RelationshipEvent`]
+    #[inline]
+    pub fn relationship_state(&self) -> Arc<Relationships> {
+        self.relationship_state.clone()
+    }
+
+    /// Replaces the relationship state used to derive presence transitions
+    #[inline]
+    pub fn seed_relationships(&self, relationships: Vec<Relationship>) {
+        self.relationship_state.replace(relationships);
+    }
 }
 
 pub struct ActivitySpoke(pub broadcast::Receiver<ActivityEvent>);
 pub struct RelationshipSpoke(pub broadcast::Receiver<RelationshipEvent>);
+pub struct PresenceSpoke(pub broadcast::Receiver<PresenceTransitionEvent>);
 pub struct UserSpoke(pub watch::Receiver<UserState>);
 pub struct OverlaySpoke(pub watch::Receiver<OverlayState>);
 
@@ -93,7 +302,7 @@ pub enum UserState {
     Disconnected(crate::Error),
 }
 
-#[derive(Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct OverlayState {
     /// Whether the user has the overlay enabled or disabled. If the overlay
     /// is disabled, all the functionality of the SDK will still work. The
@@ -108,10 +317,15 @@ pub struct OverlayState {
 pub struct WheelHandler {
     activity: broadcast::Sender<ActivityEvent>,
     relations: broadcast::Sender<RelationshipEvent>,
+    presence: broadcast::Sender<PresenceTransitionEvent>,
 
     user: watch::Sender<UserState>,
     overlay: watch::Sender<OverlayState>,
 
+    relationship_state: Arc<Relationships>,
+
+    unclaimed: Arc<Mutex<Unclaimed>>,
+
     error: Box<dyn OnError>,
 }
 
@@ -126,6 +340,14 @@ impl super::DiscordHandler for WheelHandler {
                         UserEvent::Connect(eve) => UserState::Connected(eve.user),
                         UserEvent::Update(eve) => UserState::Connected(eve.user),
                         UserEvent::Disconnect(de) => UserState::Disconnected(de.reason),
+                        UserEvent::AccountChanged(_) => {
+                            // Nothing we know about the old user applies to
+                            // the new one, the user state itself is updated
+                            // by the `Ready` that follows
+                            self.relationship_state.replace(Vec::new());
+                            self.unclaimed.lock().clear();
+                            return;
+                        }
                     };
 
                     if let Err(e) = self.user.send(us) {
@@ -133,13 +355,37 @@ impl super::DiscordHandler for WheelHandler {
                     }
                 }
                 ClassifiedEvent::Relations(re) => {
+                    for diff in self.relationship_state.on_event(re.clone()) {
+                        let (old, new) = match diff {
+                            RelationshipDiff::Added(new) => (None, new),
+                            RelationshipDiff::KindChanged { old, new } => (Some(old), new),
+                            // A change in kind is already covered by the
+                            // `KindChanged` for the same update
+                            RelationshipDiff::PresenceChanged { old, new }
+                                if old.kind == new.kind =>
+                            {
+                                (Some(old), new)
+                            }
+                            _ => continue,
+                        };
+
+                        for transition in transitions::friend_transitions(old.as_deref(), &new) {
+                            // Unlike the other spokes, not observing these is
+                            // perfectly normal
+                            let _ = self.presence.send(transition);
+                        }
+                    }
+
                     if let Err(e) = self.relations.send(re) {
                         tracing::warn!(event = ?e.0, "Relationship event was unobserved");
                     }
                 }
                 ClassifiedEvent::Activity(activity) => {
+                    // Keep the event around so that it can still be claimed
+                    // by the application, as otherwise it will eg. miss a
+                    // join entirely
                     if let Err(e) = self.activity.send(activity) {
-                        tracing::warn!(event = ?e.0, "Activity event was unobserved");
+                        self.unclaimed.lock().retain(e.0);
                     }
                 }
                 ClassifiedEvent::Overlay(overlay) => {
@@ -158,3 +404,149 @@ impl super::DiscordHandler for WheelHandler {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        relations::{RelationKind, RelationStatus, RelationshipPresence},
+        user::test_user as user,
+        DiscordHandler, Event,
+    };
+
+    #[tokio::test]
+    async fn account_changed_resets() {
+        let (wheel, handler) = Wheel::new(Box::new(|_err| {}));
+
+        wheel.seed_relationships(vec![Relationship {
+            kind: RelationKind::Friend,
+            user: user(2),
+            presence: RelationshipPresence {
+                status: RelationStatus::Online,
+                activity: None,
+            },
+        }]);
+        handler
+            .on_message(DiscordMsg::Event(Event::ActivityJoin(SecretEvent {
+                secret: "secret".to_owned(),
+            })))
+            .await;
+
+        assert_eq!(wheel.relationship_state().len(), 1);
+
+        handler
+            .on_message(DiscordMsg::Event(Event::AccountChanged {
+                old: user(1),
+                new: user(3),
+            }))
+            .await;
+
+        assert!(wheel.relationship_state().is_empty());
+        assert!(wheel.take_unclaimed_join().is_none());
+    }
+
+    #[tokio::test]
+    async fn friend_transitions() {
+        use crate::relations::transitions::PresenceTransition as PT;
+
+        let (wheel, handler) = Wheel::new(Box::new(|_err| {}));
+        let mut presence = wheel.presence().0;
+
+        let update = |id, kind, status| {
+            DiscordMsg::Event(Event::RelationshipUpdate(Arc::new(Relationship {
+                kind,
+                user: user(id),
+                presence: RelationshipPresence {
+                    status,
+                    activity: None,
+                },
+            })))
+        };
+
+        // A friend seen for the first time while already online
+        handler
+            .on_message(update(1, RelationKind::Friend, RelationStatus::Online))
+            .await;
+        // A pending friend request that is accepted while the user is online
+        handler
+            .on_message(update(
+                2,
+                RelationKind::PendingOutgoing,
+                RelationStatus::Online,
+            ))
+            .await;
+        handler
+            .on_message(update(2, RelationKind::Friend, RelationStatus::Online))
+            .await;
+        handler
+            .on_message(update(2, RelationKind::Friend, RelationStatus::Offline))
+            .await;
+
+        let mut next = || {
+            let event = presence.try_recv().unwrap();
+            (event.relationship.user.id.0, event.transition)
+        };
+
+        assert_eq!(next(), (1, PT::CameOnline));
+        assert_eq!(next(), (2, PT::CameOnline));
+        assert_eq!(next(), (2, PT::WentOffline));
+        assert!(presence.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn retains_unclaimed() {
+        let (wheel, handler) = Wheel::with_config(
+            Box::new(|_err| {}),
+            WheelConfig {
+                unclaimed_limit: 3,
+                ..Default::default()
+            },
+        );
+
+        let secret = |secret: &str| SecretEvent {
+            secret: secret.to_owned(),
+        };
+
+        for event in [
+            Event::ActivityJoin(secret("1")),
+            Event::ActivitySpectate(secret("2")),
+            Event::ActivityJoin(secret("3")),
+            Event::ActivityJoin(secret("4")),
+        ] {
+            handler.on_message(DiscordMsg::Event(event)).await;
+        }
+
+        // The oldest event is dropped once the limit is reached, and the most
+        // recent join supersedes the older one, unless a specific one is taken
+        assert!(wheel.take_unclaimed_join_matching("1").is_none());
+        assert_eq!(wheel.take_unclaimed_join_matching("3").unwrap().secret, "3");
+        assert_eq!(wheel.take_unclaimed_join().unwrap().secret, "4");
+        let unclaimed = wheel.take_unclaimed();
+        assert_eq!(unclaimed.len(), 1);
+        assert!(matches!(&unclaimed[0], ActivityEvent::Spectate(s) if s.secret == "2"));
+
+        // Events are only retained if no one is subscribed
+        let mut activity = wheel.activity().0;
+        handler
+            .on_message(DiscordMsg::Event(Event::ActivityJoin(secret("5"))))
+            .await;
+        assert!(wheel.take_unclaimed().is_empty());
+        assert!(activity.recv().await.is_ok());
+        drop(activity);
+
+        // Events expire once they've been retained for longer than the ttl,
+        // and a zero capacity doesn't panic
+        let (wheel, handler) = Wheel::with_config(
+            Box::new(|_err| {}),
+            WheelConfig {
+                capacity: 0,
+                unclaimed_ttl: Duration::ZERO,
+                ..Default::default()
+            },
+        );
+        handler
+            .on_message(DiscordMsg::Event(Event::ActivityJoin(secret("6"))))
+            .await;
+        assert!(wheel.take_unclaimed_join().is_none());
+    }
+}
@@ -10,17 +10,22 @@ pub mod overlay;
 mod proto;
 pub mod registration;
 pub mod relations;
+pub mod stream;
+mod subscriptions;
 mod types;
 pub mod user;
 
-pub use error::{DiscordApiErr, DiscordErr, Error};
-pub use handler::{handlers, wheel, DiscordHandler, DiscordMsg};
-pub use proto::event::Event;
+pub use error::{DiscordApiErr, DiscordErr, Error, JoinError};
+pub use handler::{handlers, wheel, DiscordHandler, DiscordMsg, EventHandler};
+pub use proto::event::{Event, EventKind};
 use proto::{Command, CommandKind};
+pub use subscriptions::SubscriptionStatus;
 pub use time::OffsetDateTime;
-pub use types::Snowflake;
+pub use types::{DiscordConfig, Environment, Snowflake};
 pub type AppId = i64;
 
+/// Used to implement [`DiscordHandler`] and [`EventHandler`]
+pub use async_trait::async_trait;
 pub use crossbeam_channel as cc;
 use parking_lot::Mutex;
 use std::sync::Arc;
@@ -50,19 +55,48 @@ impl From<registration::Application> for DiscordApp {
 }
 
 bitflags::bitflags! {
-    #[derive(Copy, Clone)]
+    /// The events to subscribe to. Each event can be subscribed to
+    /// individually, or in groups of related events.
+    ///
+    /// New flags may be added for new kinds of events, so this should not be
+    /// matched exhaustively, eg. via [`Subscriptions::bits`]. See
+    /// [`Subscriptions::for_event`] and [`Subscriptions::event_kinds`] to map
+    /// between subscriptions and [`EventKind`]s.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
     pub struct Subscriptions: u32 {
-        const ACTIVITY = 0x1;
-        const USER = 0x4;
-        const OVERLAY = 0x8;
-        const RELATIONSHIPS = 0x10;
+        /// [`EventKind::ActivityJoin`]
+        const ACTIVITY_JOIN = 0x1;
+        /// [`EventKind::CurrentUserUpdate`]
+        const CURRENT_USER_UPDATE = 0x4;
+        /// [`EventKind::OverlayUpdate`]
+        const OVERLAY_UPDATE = 0x8;
+        /// [`EventKind::RelationshipUpdate`]
+        const RELATIONSHIP_UPDATE = 0x10;
+        /// [`EventKind::ActivitySpectate`]
+        const ACTIVITY_SPECTATE = 0x20;
+        /// [`EventKind::ActivityJoinRequest`]
+        const ACTIVITY_JOIN_REQUEST = 0x40;
+        /// [`EventKind::ActivityInvite`]
+        const ACTIVITY_INVITE = 0x80;
 
+        /// All of the activity events
+        const ACTIVITY = Self::ACTIVITY_JOIN.bits()
+            | Self::ACTIVITY_SPECTATE.bits()
+            | Self::ACTIVITY_JOIN_REQUEST.bits()
+            | Self::ACTIVITY_INVITE.bits();
+        /// All of the user events
+        const USER = Self::CURRENT_USER_UPDATE.bits();
+        /// All of the overlay events
+        const OVERLAY = Self::OVERLAY_UPDATE.bits();
+        /// All of the relationship events
+        const RELATIONSHIPS = Self::RELATIONSHIP_UPDATE.bits();
+
+        /// Every event that can be subscribed to
         const ALL = Self::ACTIVITY.bits() | Self::USER.bits() | Self::OVERLAY.bits() | Self::RELATIONSHIPS.bits();
     }
 }
 
 pub struct Discord {
-    nonce: std::sync::atomic::AtomicUsize,
     /// Queue for messages to be sent to Discord
     send_queue: cc::Sender<Option<Vec<u8>>>,
     /// The handle to the task actually driving the I/O with Discord
@@ -74,11 +108,29 @@ pub struct Discord {
 
 impl Discord {
     /// Creates a new Discord connection for the specified application, providing
-    /// a [`DiscordHandler`] which can handle events as they arrive from Discord
+    /// a [`DiscordHandler`] which can handle events as they arrive from Discord.
+    ///
+    /// The initial subscriptions can be changed later with
+    /// [`Discord::subscribe`] and [`Discord::unsubscribe`].
     pub fn new(
         app: impl Into<DiscordApp>,
         subscriptions: Subscriptions,
         handler: Box<dyn DiscordHandler>,
+    ) -> Result<Self, Error> {
+        Self::with_target_pid(app, subscriptions, std::process::id(), handler)
+    }
+
+    /// Creates a new Discord connection, the same as [`Discord::new`], but
+    /// with the specified [target pid](Self::set_target_pid) rather than the
+    /// current process.
+    ///
+    /// Unlike calling [`Discord::set_target_pid`] after creation, this
+    /// guarantees the pid is used for the `OVERLAY_UPDATE` subscription.
+    pub fn with_target_pid(
+        app: impl Into<DiscordApp>,
+        subscriptions: Subscriptions,
+        target_pid: u32,
+        handler: Box<dyn DiscordHandler>,
     ) -> Result<Self, Error> {
         let app_id = match app.into() {
             DiscordApp::PlainId(id) => id,
@@ -92,17 +144,15 @@ impl Discord {
         let io_task = io::start_io_task(app_id);
 
         let state = State::default();
+        *state.subscriptions.lock() = subscriptions;
+        state
+            .target_pid
+            .store(target_pid, std::sync::atomic::Ordering::Relaxed);
 
-        let handler_task = handler::handler_task(
-            handler,
-            subscriptions,
-            io_task.stx.clone(),
-            io_task.rrx,
-            state.clone(),
-        );
+        let handler_task =
+            handler::handler_task(handler, io_task.stx.clone(), io_task.rrx, state.clone());
 
         Ok(Self {
-            nonce: std::sync::atomic::AtomicUsize::new(1),
             send_queue: io_task.stx,
             io_task: io_task.handle,
             handler_task,
@@ -110,6 +160,76 @@ impl Discord {
         })
     }
 
+    /// Sets the process id that RPCs which refer to a process, such as setting
+    /// the activity or opening the overlay, target by default. This is the
+    /// current process unless otherwise specified, but can be changed if eg.
+    /// a launcher process holds the connection to Discord while the game runs
+    /// in a child process.
+    ///
+    /// Note that the `OVERLAY_UPDATE` subscription uses the target pid at the
+    /// time it is subscribed to, ie. when the connection to Discord is
+    /// established, so changing the target pid only applies to it on
+    /// subsequent connections. Use [`Discord::with_target_pid`] to target a
+    /// different process from the start.
+    pub fn set_target_pid(&self, pid: u32) {
+        self.state
+            .target_pid
+            .store(pid, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// The process id that RPCs which refer to a process target by default
+    #[inline]
+    pub fn target_pid(&self) -> u32 {
+        self.state
+            .target_pid
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// The user that is logged in to the Discord application we are connected
+    /// to, or `None` if the handshake with Discord has not completed yet, or
+    /// the connection has been lost.
+    ///
+    /// This is kept up to date with [`Event::CurrentUserUpdate`] if the
+    /// [`Subscriptions::USER`] subscription is used.
+    pub fn current_user(&self) -> Option<user::User> {
+        self.state
+            .session
+            .borrow()
+            .as_ref()
+            .map(|session| session.user.clone())
+    }
+
+    /// The configuration of the Discord application we are connected to, or
+    /// `None` if the handshake with Discord has not completed yet, or the
+    /// connection has been lost.
+    pub fn config(&self) -> Option<DiscordConfig> {
+        self.state
+            .session
+            .borrow()
+            .as_ref()
+            .map(|session| session.config.clone())
+    }
+
+    /// Waits until the handshake with Discord has completed, returning the
+    /// [current user](Self::current_user). Resolves immediately if the
+    /// handshake has already completed.
+    ///
+    /// # Errors
+    /// Fails with [`Error::TimedOut`] if the handshake did not complete within
+    /// the timeout
+    pub async fn wait_ready(&self, timeout: std::time::Duration) -> Result<user::User, Error> {
+        let mut session = self.state.session.subscribe();
+
+        let session = tokio::time::timeout(timeout, session.wait_for(Option::is_some))
+            .await?
+            .map_err(|_err| Error::ChannelDisconnected)?;
+
+        session
+            .as_ref()
+            .map(|session| session.user.clone())
+            .ok_or(Error::NoConnection)
+    }
+
     /// Disconnects from Discord, shutting down the tasks that have been created
     /// to handle sending and receiving messages from it.
     pub async fn disconnect(self) {
@@ -128,31 +248,17 @@ impl Discord {
     where
         Msg: serde::Serialize,
     {
-        // Increment the nonce, we use this in the handler task to pair the response
-        // to this request
-        let nonce = self
-            .nonce
-            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-
-        let rpc = proto::Rpc {
-            cmd,
-            args: Some(msg),
-            nonce: nonce.to_string(),
-            evt: None,
-        };
-
-        let (tx, rx) = tokio::sync::oneshot::channel();
-
         self.state
-            .notify_queue
-            .lock()
-            .push(NotifyItem { nonce, tx, cmd });
-
-        let mut buffer = Vec::with_capacity(128);
-        io::serialize_message(io::OpCode::Frame, &rpc, &mut buffer)?;
-        self.send_queue.send(Some(buffer))?;
+            .send_raw_rpc(&self.send_queue, cmd, |nonce, buffer| {
+                let rpc = proto::Rpc {
+                    cmd,
+                    args: Some(msg),
+                    nonce: nonce.to_string(),
+                    evt: None,
+                };
 
-        Ok(rx)
+                io::serialize_message(io::OpCode::Frame, &rpc, buffer)
+            })
     }
 }
 
@@ -171,14 +277,70 @@ pub(crate) struct NotifyItem {
 /// State shared between the top level [`Discord`] object and the handler task
 #[derive(Clone)]
 pub(crate) struct State {
+    /// The nonce for the next RPC sent to Discord
+    nonce: Arc<std::sync::atomic::AtomicUsize>,
     /// Queue of RPCs sent to Discord that are awaiting a response
     notify_queue: Arc<Mutex<Vec<NotifyItem>>>,
+    /// The process id that RPCs target by default
+    target_pid: Arc<std::sync::atomic::AtomicU32>,
+    /// The details of the current connection, only set once the handshake with
+    /// Discord has completed
+    session: Arc<tokio::sync::watch::Sender<Option<Session>>>,
+    /// The most recent overlay state
+    overlay: Arc<tokio::sync::watch::Sender<overlay::OverlayWatch>>,
+    /// Broadcasts of events for [`Discord::events`]
+    events: stream::EventBus,
+    /// The events to subscribe to each time a connection is established
+    subscriptions: Arc<Mutex<Subscriptions>>,
+    /// The status of the subscription to each event on the current connection
+    subscription_status: Arc<Mutex<std::collections::HashMap<EventKind, SubscriptionStatus>>>,
+}
+
+impl State {
+    /// Sends an RPC serialized by the caller with the specified nonce, for RPCs
+    /// which don't follow the usual form, eg. un/subscribe
+    fn send_raw_rpc(
+        &self,
+        send_queue: &cc::Sender<Option<Vec<u8>>>,
+        cmd: CommandKind,
+        serialize: impl FnOnce(usize, &mut Vec<u8>) -> Result<(), Error>,
+    ) -> Result<tokio::sync::oneshot::Receiver<Result<Command, Error>>, Error> {
+        // Increment the nonce, we use this in the handler task to pair the response
+        // to this request
+        let nonce = self
+            .nonce
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+        let mut buffer = Vec::with_capacity(128);
+        serialize(nonce, &mut buffer)?;
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        self.notify_queue.lock().push(NotifyItem { nonce, tx, cmd });
+
+        send_queue.send(Some(buffer))?;
+
+        Ok(rx)
+    }
+}
+
+/// The details Discord sends about itself once the handshake has completed
+pub(crate) struct Session {
+    pub(crate) user: user::User,
+    pub(crate) config: DiscordConfig,
 }
 
 impl Default for State {
     fn default() -> Self {
         Self {
+            nonce: Arc::new(std::sync::atomic::AtomicUsize::new(1)),
             notify_queue: Arc::new(Mutex::new(Vec::new())),
+            target_pid: Arc::new(std::sync::atomic::AtomicU32::new(std::process::id())),
+            session: Arc::new(tokio::sync::watch::channel(None).0),
+            overlay: Arc::new(tokio::sync::watch::channel(Default::default()).0),
+            events: stream::EventBus::default(),
+            subscriptions: Arc::new(Mutex::new(Subscriptions::empty())),
+            subscription_status: Arc::new(Mutex::new(std::collections::HashMap::new())),
         }
     }
 }
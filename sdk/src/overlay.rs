@@ -2,7 +2,7 @@
 
 pub mod events;
 
-use crate::{Command, CommandKind, Error};
+use crate::{error::InviteCodeError, Command, CommandKind, Error};
 use serde::Serialize;
 
 #[derive(Serialize)]
@@ -15,11 +15,8 @@ struct OverlayToggle {
 }
 
 impl OverlayToggle {
-    fn new(visibility: Visibility) -> Self {
-        Self {
-            pid: std::process::id(),
-            visibility,
-        }
+    fn new(visibility: Visibility, pid: u32) -> Self {
+        Self { pid, visibility }
     }
 }
 
@@ -83,22 +80,268 @@ pub(crate) struct OverlayPidArgs {
 }
 
 impl OverlayPidArgs {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(pid: u32) -> Self {
+        Self { pid }
+    }
+}
+
+/// A validated Discord guild invite code, eg. `rust-lang` from
+/// `https://discord.gg/rust-lang`
+///
+/// Can be parsed from either a plain code, or any of the invite url forms
+/// Discord uses, with or without a scheme.
+///
+/// ```
+/// use discord_sdk::overlay::InviteCode;
+///
+/// for invite in [
+///     "rust-lang",
+///     "discord.gg/rust-lang",
+///     "https://discord.com/invite/rust-lang/",
+///     "http://www.discordapp.com/invite/rust-lang?event=1#top",
+/// ] {
+///     let code: InviteCode = invite.parse().unwrap();
+///     assert_eq!(code.as_str(), "rust-lang");
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InviteCode(String);
+
+impl InviteCode {
+    /// The maximum length of an invite code, which is the limit for vanity
+    /// urls, as regular invite codes are much shorter
+    const MAX_LEN: usize = 32;
+
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    fn validate(code: &str) -> Result<Self, InviteCodeError> {
+        if code.is_empty() {
+            return Err(InviteCodeError::Empty);
+        }
+
+        if code.len() > Self::MAX_LEN {
+            return Err(InviteCodeError::TooLong(code.len()));
+        }
+
+        if let Some((offset, ch)) = code
+            .char_indices()
+            .find(|(_, ch)| !ch.is_ascii_alphanumeric() && *ch != '-')
+        {
+            return Err(InviteCodeError::InvalidCharacter { ch, offset });
+        }
+
+        Ok(Self(code.to_owned()))
+    }
+}
+
+impl std::str::FromStr for InviteCode {
+    type Err = InviteCodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        // Anything that looks like it has a host is treated as a url
+        if !s.contains(['/', '.']) {
+            return Self::validate(s);
+        }
+
+        let url = if s.contains("://") {
+            url::Url::parse(s)
+        } else {
+            url::Url::parse(&format!("https://{s}"))
+        }
+        .map_err(|_err| InviteCodeError::InvalidUrl)?;
+
+        Self::try_from(&url)
+    }
+}
+
+impl TryFrom<&url::Url> for InviteCode {
+    type Error = InviteCodeError;
+
+    fn try_from(url: &url::Url) -> Result<Self, Self::Error> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(InviteCodeError::InvalidUrl);
+        }
+
+        let host = url.host_str().ok_or(InviteCodeError::InvalidUrl)?;
+        let host = host.strip_prefix("www.").unwrap_or(host);
+
+        let mut segments = url
+            .path_segments()
+            .into_iter()
+            .flatten()
+            .filter(|seg| !seg.is_empty());
+
+        let code = match host {
+            "discord.gg" => segments.next(),
+            "discord.com" | "discordapp.com" | "ptb.discord.com" | "canary.discord.com" => {
+                match segments.next() {
+                    Some("invite") => segments.next(),
+                    _ => None,
+                }
+            }
+            unknown => return Err(InviteCodeError::UnknownHost(unknown.to_owned())),
+        };
+
+        match (code, segments.next()) {
+            (Some(code), None) => Self::validate(code),
+            _ => Err(InviteCodeError::InvalidUrl),
+        }
+    }
+}
+
+impl AsRef<str> for InviteCode {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for InviteCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// What to do when calling an overlay method that is known to crash Discord
+/// if the overlay is not available, eg. because it is disabled by the user, or
+/// the application uses Vulkan
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OverlayFallback {
+    /// Fail with [`Error::OverlayUnavailable`] without sending anything to
+    /// Discord
+    Fail,
+    /// Focus the Discord client instead, see [`crate::Discord::set_overlay_visibility`]
+    FocusDiscord,
+    /// Send the call regardless, only use this if you know the overlay is
+    /// available even though Discord hasn't said so
+    Force,
+}
+
+/// What a guarded overlay call should actually do
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Guarded {
+    Send,
+    Focus,
+    Fail,
+}
+
+impl OverlayFallback {
+    fn guard(self, state: Option<crate::wheel::OverlayState>) -> Guarded {
+        // We only trust the overlay to be available if Discord has told us
+        // so, which requires the OVERLAY subscription
+        if self == Self::Force || state.is_some_and(|state| state.enabled) {
+            Guarded::Send
+        } else if self == Self::FocusDiscord {
+            Guarded::Focus
+        } else {
+            Guarded::Fail
+        }
+    }
+}
+
+/// The overlay state shared between the handler task and the [`Discord`](crate::Discord)
+#[derive(Default)]
+pub(crate) struct OverlayWatch {
+    /// The most recent state sent by Discord, `None` if we haven't received
+    /// one, or the connection was lost
+    pub(crate) state: Option<crate::wheel::OverlayState>,
+    /// The number of times the overlay has been closed, or the connection
+    /// lost, which lets an [`OverlayModal`] detect that the overlay closed even
+    /// if it opened and closed again before being polled
+    pub(crate) closes: u64,
+}
+
+impl OverlayWatch {
+    pub(crate) fn update(&mut self, state: Option<crate::wheel::OverlayState>) {
+        let was_visible = self
+            .state
+            .is_some_and(|state| state.visible == Visibility::Visible);
+        let is_visible = state.is_some_and(|state| state.visible == Visibility::Visible);
+
+        if was_visible && !is_visible || state.is_none() {
+            self.closes += 1;
+        }
+
+        self.state = state;
+    }
+}
+
+/// A handle to a modal opened in the overlay, which can be used to wait until
+/// the user has closed the overlay again, eg. to pause game input while the
+/// modal is up.
+pub struct OverlayModal {
+    rx: tokio::sync::watch::Receiver<OverlayWatch>,
+    /// The number of overlay closes before the modal was opened
+    closes: u64,
+    /// Whether we can expect an overlay update when the modal closes
+    tracked: bool,
+}
+
+impl OverlayModal {
+    fn new(rx: tokio::sync::watch::Receiver<OverlayWatch>) -> Self {
+        let (closes, tracked) = {
+            let watch = rx.borrow();
+            (watch.closes, watch.state.is_some_and(|state| state.enabled))
+        };
+
         Self {
-            pid: std::process::id(),
+            rx,
+            closes,
+            tracked,
+        }
+    }
+
+    /// Waits until the overlay has been closed.
+    ///
+    /// If the overlay is disabled, the modal is shown in the Discord client
+    /// itself rather than in the overlay, in which case it is not possible
+    /// to know when it is closed, and this resolves immediately. This also
+    /// applies if the [`Subscriptions::OVERLAY`](crate::Subscriptions::OVERLAY)
+    /// subscription is not used, as the overlay state is not known.
+    ///
+    /// This also resolves if the connection to Discord is lost.
+    pub async fn wait_closed(mut self) {
+        if !self.tracked {
+            return;
         }
+
+        let closes = self.closes;
+        let _ = self.rx.wait_for(|watch| watch.closes > closes).await;
     }
 }
 
 impl crate::Discord {
+    /// The most recent state of the overlay, `None` if Discord hasn't sent it
+    /// yet, which requires the [`Subscriptions::OVERLAY`](crate::Subscriptions::OVERLAY)
+    /// subscription.
+    pub fn overlay_state(&self) -> Option<crate::wheel::OverlayState> {
+        self.state.overlay.borrow().state
+    }
+
     /// Opens or closes the overlay. If the overlay is not enabled this will
     /// instead focus the Discord app itself.
     ///
     /// [API docs](https://discord.com/developers/docs/game-sdk/overlay#setlocked)
     pub async fn set_overlay_visibility(&self, visibility: Visibility) -> Result<(), Error> {
+        self.set_overlay_visibility_for_pid(visibility, self.target_pid())
+            .await
+    }
+
+    /// The same as [`Self::set_overlay_visibility`], but for the overlay in
+    /// the specified process rather than the [target pid](Self::set_target_pid).
+    pub async fn set_overlay_visibility_for_pid(
+        &self,
+        visibility: Visibility,
+        pid: u32,
+    ) -> Result<(), Error> {
         let rx = self.send_rpc(
             CommandKind::SetOverlayVisibility,
-            OverlayToggle::new(visibility),
+            OverlayToggle::new(visibility, pid),
         )?;
 
         handle_response!(rx, Command::SetOverlayVisibility => {
@@ -115,8 +358,22 @@ impl crate::Discord {
     /// [Activity Action Field Requirements](https://discord.com/developers/docs/game-sdk/activities#activity-action-field-requirements)
     /// for the fields required to have join and spectate invites function properly.
     ///
+    /// The returned [`OverlayModal`] can be used to wait until the modal has
+    /// been closed.
+    ///
     /// [API docs](https://discord.com/developers/docs/game-sdk/overlay#openactivityinvite)
-    pub async fn open_activity_invite(&self, action: InviteAction) -> Result<(), Error> {
+    pub async fn open_activity_invite(&self, action: InviteAction) -> Result<OverlayModal, Error> {
+        self.open_activity_invite_for_pid(action, self.target_pid())
+            .await
+    }
+
+    /// The same as [`Self::open_activity_invite`], but for the overlay in
+    /// the specified process rather than the [target pid](Self::set_target_pid).
+    pub async fn open_activity_invite_for_pid(
+        &self,
+        action: InviteAction,
+        pid: u32,
+    ) -> Result<OverlayModal, Error> {
         #[derive(Serialize)]
         struct OpenInviteModal {
             /// Our process id, this lets Discord know what process it should try
@@ -126,39 +383,47 @@ impl crate::Discord {
             kind: InviteAction,
         }
 
+        // Subscribe before sending the RPC so that we can't miss the overlay
+        // being closed
+        let modal = OverlayModal::new(self.state.overlay.subscribe());
+
         let rx = self.send_rpc(
             CommandKind::OpenOverlayActivityInvite,
-            OpenInviteModal {
-                pid: std::process::id(),
-                kind: action,
-            },
+            OpenInviteModal { pid, kind: action },
         )?;
 
         handle_response!(rx, Command::OpenOverlayActivityInvite => {
-            Ok(())
+            Ok(modal)
         })
     }
 
     /// Opens the overlay modal for joining a Discord guild, given its invite code.
-    /// Unlike the normal SDK, this method automatically parses the code from
-    /// the provided string so you don't need to do it yourself.
+    /// Unlike the normal SDK, this method accepts any of the invite url forms
+    /// as well as plain codes, see [`InviteCode`].
     ///
     /// Note that just because the result might be [`Result::Ok`] doesn't
-    /// necessarily mean the user accepted the invite.
+    /// necessarily mean the user accepted the invite. The returned
+    /// [`OverlayModal`] can be used to wait until the modal has been closed.
+    ///
+    /// # Errors
+    /// Fails with [`Error::InvalidInviteCode`] if the code is not a valid
+    /// invite code or url
     ///
     /// [API docs](https://discord.com/developers/docs/game-sdk/overlay#openguildinvite)
-    pub async fn open_guild_invite(&self, code: impl AsRef<str>) -> Result<(), Error> {
-        let mut code = code.as_ref();
-
-        if let Some(rest) = code.strip_prefix("https://") {
-            code = rest;
-        }
+    pub async fn open_guild_invite(&self, code: impl AsRef<str>) -> Result<OverlayModal, Error> {
+        self.open_guild_invite_for_pid(code, self.target_pid())
+            .await
+    }
 
-        if let Some(rest) = code.strip_prefix("discord.gg/") {
-            code = rest;
-        } else if let Some(rest) = code.strip_prefix("discordapp.com/invite/") {
-            code = rest;
-        }
+    /// The same as [`Self::open_guild_invite`], but for the overlay in
+    /// the specified process rather than the [target pid](Self::set_target_pid).
+    pub async fn open_guild_invite_for_pid(
+        &self,
+        code: impl AsRef<str>,
+        pid: u32,
+    ) -> Result<OverlayModal, Error> {
+        let code: InviteCode = code.as_ref().parse()?;
+        let code = code.as_str();
 
         #[derive(Serialize)]
         struct OpenGuildInviteModal<'stack> {
@@ -166,27 +431,180 @@ impl crate::Discord {
             code: &'stack str,
         }
 
+        let modal = OverlayModal::new(self.state.overlay.subscribe());
+
         let rx = self.send_rpc(
             CommandKind::OpenOverlayGuildInvite,
-            OpenGuildInviteModal {
-                pid: std::process::id(),
-                code,
-            },
+            OpenGuildInviteModal { pid, code },
         )?;
 
         handle_response!(rx, Command::OpenOverlayGuildInvite => {
-            Ok(())
+            Ok(modal)
         })
     }
 
     /// Opens the overlay widget for voice settings for the currently connected application.
     ///
+    /// Sending this when the overlay is not available can crash Discord, so
+    /// this is only sent if Discord has reported that the overlay is enabled,
+    /// which requires the [`Subscriptions::OVERLAY`](crate::Subscriptions::OVERLAY)
+    /// subscription. See [`Self::open_voice_settings_with`] to change this.
+    ///
+    /// # Errors
+    /// Fails with [`Error::OverlayUnavailable`] if the overlay is not known to
+    /// be enabled
+    ///
     /// [API docs](https://discord.com/developers/docs/game-sdk/overlay#openvoicesettings)
     pub async fn open_voice_settings(&self) -> Result<(), Error> {
-        let rx = self.send_rpc(CommandKind::OpenOverlayVoiceSettings, OverlayPidArgs::new())?;
+        self.open_voice_settings_with(OverlayFallback::Fail).await
+    }
+
+    /// The same as [`Self::open_voice_settings`], but with the specified
+    /// fallback if the overlay is not known to be enabled
+    pub async fn open_voice_settings_with(&self, fallback: OverlayFallback) -> Result<(), Error> {
+        self.open_voice_settings_for_pid(self.target_pid(), fallback)
+            .await
+    }
+
+    /// The same as [`Self::open_voice_settings_with`], but for the overlay in
+    /// the specified process rather than the [target pid](Self::set_target_pid).
+    pub async fn open_voice_settings_for_pid(
+        &self,
+        pid: u32,
+        fallback: OverlayFallback,
+    ) -> Result<(), Error> {
+        match fallback.guard(self.overlay_state()) {
+            Guarded::Send => {}
+            Guarded::Focus => {
+                return self
+                    .set_overlay_visibility_for_pid(Visibility::Visible, pid)
+                    .await;
+            }
+            Guarded::Fail => return Err(Error::OverlayUnavailable),
+        }
+
+        let rx = self.send_rpc(
+            CommandKind::OpenOverlayVoiceSettings,
+            OverlayPidArgs::new(pid),
+        )?;
 
         handle_response!(rx, Command::OpenOverlayVoiceSettings => {
             Ok(())
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::wheel::OverlayState;
+
+    fn state(visible: Visibility) -> Option<OverlayState> {
+        Some(OverlayState {
+            enabled: true,
+            visible,
+        })
+    }
+
+    #[test]
+    fn invite_codes() {
+        for valid in [
+            "abcDEF12",
+            "  discord.gg/abcDEF12  ",
+            "https://discord.gg/abcDEF12/",
+            "http://discord.com/invite/abcDEF12",
+            "discordapp.com/invite/abcDEF12?utm=1",
+            "https://www.discord.com/invite/abcDEF12#fragment",
+            "canary.discord.com/invite/abcDEF12",
+        ] {
+            assert_eq!(
+                valid.parse::<InviteCode>().unwrap().as_str(),
+                "abcDEF12",
+                "{valid}"
+            );
+        }
+
+        let url = url::Url::parse("https://discord.gg/rust-lang").unwrap();
+        assert_eq!(InviteCode::try_from(&url).unwrap().as_str(), "rust-lang");
+
+        assert!(matches!(
+            "".parse::<InviteCode>(),
+            Err(InviteCodeError::Empty)
+        ));
+        assert!(matches!(
+            "abc def".parse::<InviteCode>(),
+            Err(InviteCodeError::InvalidCharacter { ch: ' ', offset: 3 })
+        ));
+        assert!(matches!(
+            "discord.gg/".parse::<InviteCode>(),
+            Err(InviteCodeError::InvalidUrl)
+        ));
+        assert!(matches!(
+            "discord.com/channels/abc".parse::<InviteCode>(),
+            Err(InviteCodeError::InvalidUrl)
+        ));
+        assert!(matches!(
+            "discord.gg/abc/def".parse::<InviteCode>(),
+            Err(InviteCodeError::InvalidUrl)
+        ));
+        assert!(matches!(
+            "ftp://discord.gg/abc".parse::<InviteCode>(),
+            Err(InviteCodeError::InvalidUrl)
+        ));
+        assert!(matches!(
+            "example.com/invite/abc".parse::<InviteCode>(),
+            Err(InviteCodeError::UnknownHost(host)) if host == "example.com"
+        ));
+        assert!(matches!(
+            "a".repeat(33).parse::<InviteCode>(),
+            Err(InviteCodeError::TooLong(33))
+        ));
+    }
+
+    #[test]
+    fn guards() {
+        let disabled = Some(OverlayState {
+            enabled: false,
+            visible: Visibility::Hidden,
+        });
+
+        for state in [None, disabled] {
+            assert_eq!(OverlayFallback::Fail.guard(state), Guarded::Fail);
+            assert_eq!(OverlayFallback::FocusDiscord.guard(state), Guarded::Focus);
+            assert_eq!(OverlayFallback::Force.guard(state), Guarded::Send);
+        }
+
+        let enabled = state(Visibility::Hidden);
+        assert_eq!(OverlayFallback::Fail.guard(enabled), Guarded::Send);
+        assert_eq!(OverlayFallback::FocusDiscord.guard(enabled), Guarded::Send);
+    }
+
+    #[tokio::test]
+    async fn modal_closes() {
+        let (tx, rx) = tokio::sync::watch::channel(OverlayWatch::default());
+
+        // The overlay state is unknown so the modal can't be tracked
+        OverlayModal::new(rx.clone()).wait_closed().await;
+
+        tx.send_modify(|watch| watch.update(state(Visibility::Hidden)));
+        let modal = OverlayModal::new(rx.clone());
+
+        // The overlay is opened and closed before we start waiting
+        tx.send_modify(|watch| watch.update(state(Visibility::Visible)));
+        tx.send_modify(|watch| watch.update(state(Visibility::Hidden)));
+
+        tokio::time::timeout(std::time::Duration::from_millis(10), modal.wait_closed())
+            .await
+            .expect("modal should have been closed");
+
+        // The overlay is still open
+        tx.send_modify(|watch| watch.update(state(Visibility::Visible)));
+        let modal = OverlayModal::new(rx);
+
+        assert!(
+            tokio::time::timeout(std::time::Duration::from_millis(10), modal.wait_closed())
+                .await
+                .is_err()
+        );
+    }
+}
@@ -1,7 +1,7 @@
 use super::Visibility;
 use serde::Deserialize;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 #[cfg_attr(test, derive(serde::Serialize))]
 pub struct UpdateEvent {
     /// Whether the user has the overlay enabled or disabled. If the overlay
@@ -2,7 +2,8 @@ pub(crate) mod command;
 pub(crate) mod event;
 
 pub(crate) use command::{Command, CommandKind};
-pub(crate) use event::{Event, EventKind};
+pub(crate) use event::Event;
+pub use event::EventKind;
 
 #[derive(serde::Serialize)]
 pub(crate) struct Rpc<T> {
@@ -35,6 +35,9 @@ pub enum CommandKind {
 
     /// RPC sent to retrieve the full list of a user's active relationships
     GetRelationships,
+
+    /// RPC sent to retrieve information about a user
+    GetUser,
 }
 
 /// The response to an RPC sent by us.
@@ -44,6 +47,9 @@ pub(crate) enum Command {
     Subscribe {
         evt: super::EventKind,
     },
+    Unsubscribe {
+        evt: super::EventKind,
+    },
 
     SetActivity(Box<Option<crate::activity::SetActivity>>),
     ActivityInviteUser,
@@ -60,6 +66,8 @@ pub(crate) enum Command {
     GetRelationships {
         relationships: Vec<crate::relations::Relationship>,
     },
+
+    GetUser(crate::user::User),
 }
 
 /// An RPC sent from Discord as JSON, in response to an RPC sent by us.
@@ -5,21 +5,31 @@ use crate::{
 use serde::{Deserialize, Serialize};
 
 /// Events sent from Discord when some action occurs
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-pub(crate) enum EventKind {
+#[non_exhaustive]
+pub enum EventKind {
+    /// [`Event::Ready`]
     Ready,
+    /// [`Event::Error`]
     Error,
 
+    /// [`Event::CurrentUserUpdate`]
     CurrentUserUpdate,
 
+    /// [`Event::ActivityJoinRequest`]
     ActivityJoinRequest,
+    /// [`Event::ActivityJoin`]
     ActivityJoin,
+    /// [`Event::ActivitySpectate`]
     ActivitySpectate,
+    /// [`Event::ActivityInvite`]
     ActivityInvite,
 
+    /// [`Event::OverlayUpdate`]
     OverlayUpdate,
 
+    /// [`Event::RelationshipUpdate`]
     RelationshipUpdate,
 }
 
@@ -47,6 +57,19 @@ pub enum Event {
     /// closed, etc.
     #[serde(skip)]
     Disconnected { reason: crate::Error },
+    /// Fired when the user in a [`Event::Ready`] is a different user than the
+    /// one that was logged in before the connection was re-established, eg.
+    /// because the user logged in to another account, or a different Discord
+    /// instance took over. This is a synthesized event that is sent right
+    /// before the `Ready` for the new user.
+    ///
+    /// Any state that was tied to the old user, such as relationships and
+    /// invites, no longer applies.
+    #[serde(skip)]
+    AccountChanged {
+        old: crate::user::User,
+        new: crate::user::User,
+    },
     /// Fired when any details on the current logged in user are changed.
     ///
     /// [API docs](https://discord.com/developers/docs/game-sdk/users#oncurrentuserupdate)
@@ -101,6 +124,31 @@ pub(crate) struct EventFrame {
     pub(crate) inner: Event,
 }
 
+impl Event {
+    /// Copies the event, moving any error it contains into an
+    /// [`Error::Shared`](crate::Error::Shared) so that it can be copied as well
+    pub(crate) fn share(&mut self) -> Self {
+        match self {
+            Self::Error(payload) => Self::Error(payload.clone()),
+            Self::Ready(ready) => Self::Ready(ready.clone()),
+            Self::Disconnected { reason } => Self::Disconnected {
+                reason: reason.share(),
+            },
+            Self::AccountChanged { old, new } => Self::AccountChanged {
+                old: old.clone(),
+                new: new.clone(),
+            },
+            Self::CurrentUserUpdate(update) => Self::CurrentUserUpdate(update.clone()),
+            Self::ActivityJoin(secret) => Self::ActivityJoin(secret.clone()),
+            Self::ActivitySpectate(secret) => Self::ActivitySpectate(secret.clone()),
+            Self::ActivityJoinRequest(jr) => Self::ActivityJoinRequest(jr.clone()),
+            Self::ActivityInvite(invite) => Self::ActivityInvite(invite.clone()),
+            Self::OverlayUpdate(update) => Self::OverlayUpdate(update.clone()),
+            Self::RelationshipUpdate(rel) => Self::RelationshipUpdate(rel.clone()),
+        }
+    }
+}
+
 pub enum ClassifiedEvent {
     User(user_events::UserEvent),
     Activity(activity_events::ActivityEvent),
@@ -120,6 +168,12 @@ impl From<Event> for ClassifiedEvent {
                 Self::User(UE::Disconnect(user_events::DisconnectEvent { reason }))
             }
             Event::CurrentUserUpdate(user) => Self::User(UE::Update(user)),
+            Event::AccountChanged { old, new } => {
+                Self::User(UE::AccountChanged(user_events::AccountChangedEvent {
+                    old,
+                    new,
+                }))
+            }
 
             // Activity
             Event::ActivityJoin(secret) => Self::Activity(AE::Join(secret)),
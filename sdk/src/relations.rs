@@ -1,7 +1,9 @@
 //! Provides types and functionality for [Relationships](https://discord.com/developers/docs/game-sdk/relationships)
 
 pub mod events;
+pub mod joinable;
 pub mod state;
+pub mod transitions;
 
 use crate::{user::User, Error};
 use serde::Deserialize;
@@ -44,7 +46,7 @@ pub enum RelationStatus {
 /// The start and end timestamp of the activity. These are unix timestamps.
 ///
 /// [API docs](https://discord.com/developers/docs/game-sdk/activities#data-models-activitytimestamps-struct)
-#[derive(Default, Clone, Debug, Deserialize)]
+#[derive(Default, Clone, Debug, PartialEq, Eq, Deserialize)]
 #[cfg_attr(test, derive(Serialize))]
 pub struct RelationshipActivityTimestamps {
     #[serde(
@@ -63,12 +65,42 @@ pub struct RelationshipActivityTimestamps {
 
 use crate::activity;
 
-#[derive(Default, Clone, Debug, Deserialize)]
+/// The emoji of a custom status
+///
+/// [API docs](https://discord.com/developers/docs/topics/gateway-events#activity-object-activity-emoji)
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+pub struct ActivityEmoji {
+    /// The name of the emoji, or the emoji itself for unicode emojis
+    pub name: String,
+    /// The id of the emoji, only set for custom emojis
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<crate::types::Snowflake>,
+    /// Whether the emoji is animated
+    #[serde(default)]
+    pub animated: bool,
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Eq, Deserialize)]
 #[cfg_attr(test, derive(Serialize))]
 pub struct RelationshipActivity {
+    /// The identifier for the activity, note this is not necessarily unique
+    /// between different users
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The name of the activity, eg. the name of the game or `"Spotify"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
     /// The unique identifier for the activity
     #[serde(skip_serializing_if = "Option::is_none")]
     pub session_id: Option<String>,
+    /// The application the activity belongs to, if any
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "crate::util::string::deserialize_opt"
+    )]
+    pub application_id: Option<crate::AppId>,
     /// The timestamp the activity was created
     #[serde(skip_serializing, with = "crate::util::datetime_opt")]
     pub created_at: Option<time::OffsetDateTime>,
@@ -95,9 +127,25 @@ pub struct RelationshipActivity {
     #[serde(default)]
     /// Whether this activity is an instanced context, like a match
     pub instance: bool,
+    /// What the activity supports
+    #[serde(default, skip_serializing_if = "activity::ActivityFlags::is_empty")]
+    pub flags: activity::ActivityFlags,
+    /// The buttons shown on the activity, only the labels of the buttons of
+    /// other users are available
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub buttons: Vec<activity::ButtonKind>,
+    /// The emoji of the user's custom status
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emoji: Option<ActivityEmoji>,
+    /// The id of the synced content, eg. the Spotify track
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync_id: Option<String>,
+    /// The platform the activity is on, eg. `"desktop"` or `"xbox"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 #[cfg_attr(test, derive(Serialize))]
 pub struct RelationshipPresence {
     pub status: RelationStatus,
@@ -146,6 +194,16 @@ mod test {
         insta::assert_json_snapshot!(update);
     }
 
+    #[test]
+    fn deserializes_full() {
+        let event = r#"{"cmd":"DISPATCH","data":{"type":1,"user":{"id":"682969165652689005","username":"jake.shadle","discriminator":"7557","avatar":"15bbd75c8ee6610d045852e7ea998a35","bot":false},"presence":{"status":"online","activity":{"created_at":"1632819046295","id":"e92ece5eb4ce629","name":"Ark [dev debug]","application_id":"310270644849737729","flags":3,"buttons":["Watch","Website"],"emoji":{"name":"🦀","animated":false},"sync_id":"3AHqaOkEFKZ6zEHdiplIv7","platform":"desktop","timestamps":{"start":"1632819046199"},"type":0}}},"evt":"RELATIONSHIP_UPDATE","nonce":null}"#;
+
+        let update: crate::proto::event::EventFrame =
+            serde_json::from_str(event).expect("failed to deserialize");
+
+        insta::assert_json_snapshot!(update);
+    }
+
     #[test]
     fn serde() {
         let eve = event::EventFrame {
@@ -154,17 +212,26 @@ mod test {
                 user: User {
                     id: crate::types::Snowflake(123414231424),
                     username: "name".to_owned(),
+                    global_name: Some("Name".to_owned()),
                     discriminator: Some(52),
-                    avatar: Some(crate::user::Avatar([
-                        0xf6, 0x2f, 0x2a, 0x75, 0x5c, 0xb1, 0x8c, 0x94, 0xdc, 0x5c, 0xda, 0x94,
-                        0x44, 0x10, 0x24, 0xf1,
-                    ])),
+                    avatar: Some(crate::user::Avatar {
+                        md5: [
+                            0xf6, 0x2f, 0x2a, 0x75, 0x5c, 0xb1, 0x8c, 0x94, 0xdc, 0x5c, 0xda, 0x94,
+                            0x44, 0x10, 0x24, 0xf1,
+                        ],
+                        is_animated: false,
+                    }),
                     is_bot: false,
+                    flags: crate::user::UserFlags::empty(),
+                    premium_type: crate::user::PremiumType::Nitro,
                 },
                 presence: RelationshipPresence {
                     status: RelationStatus::DoNotDisturb,
                     activity: Some(RelationshipActivity {
+                        id: Some("spotify:1".to_owned()),
+                        name: Some("Spotify".to_owned()),
                         session_id: Some("6bb1ddaea510750e905615286709d632".to_owned()),
+                        application_id: None,
                         created_at: Some(crate::util::timestamp(1628629162447)),
                         assets: Some(activity::Assets {
                             large_image: Some(
@@ -188,6 +255,11 @@ mod test {
                             start: Some(crate::util::timestamp(1628629161811)),
                             end: Some(crate::util::timestamp(1628629327961)),
                         }),
+                        flags: activity::ActivityFlags::SYNC | activity::ActivityFlags::PLAY,
+                        buttons: Vec::new(),
+                        emoji: None,
+                        sync_id: Some("3AHqaOkEFKZ6zEHdiplIv7".to_owned()),
+                        platform: Some("desktop".to_owned()),
                     }),
                 },
             })),
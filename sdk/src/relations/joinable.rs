@@ -0,0 +1,211 @@
+//! Provides discovery of friends whose game the current user can join directly
+
+use super::{state::Relationships, RelationKind, Relationship};
+use crate::{
+    activity::events::{ActivityEvent, SecretEvent},
+    wheel::Wheel,
+};
+use std::sync::Arc;
+
+/// A friend who is currently playing the application in a party that has room
+/// for more players and can be joined with a join secret
+#[derive(Debug, Clone)]
+pub struct JoinableFriend {
+    pub relationship: Arc<Relationship>,
+    /// The id of the party the friend is in
+    pub party_id: String,
+    /// The current and maximum size of the party
+    pub party_size: (u32, u32),
+    /// The secret used to join the friend's game
+    pub join_secret: String,
+    /// When the friend's session started, taken from the activity's start
+    /// timestamp, or when the activity was created if it doesn't have one
+    pub started_at: Option<time::OffsetDateTime>,
+}
+
+impl JoinableFriend {
+    fn from_relationship(relationship: &Arc<Relationship>, app_id: crate::AppId) -> Option<Self> {
+        if relationship.kind != RelationKind::Friend {
+            return None;
+        }
+
+        let activity = relationship.presence.activity.as_ref()?;
+        if activity.application_id != Some(app_id) || !activity.is_joinable() {
+            return None;
+        }
+
+        let party = activity.party.as_ref()?;
+        let join_secret = activity.secrets.as_ref()?.join.clone()?;
+
+        Some(Self {
+            relationship: relationship.clone(),
+            party_id: party.id.clone(),
+            party_size: party.size?,
+            join_secret,
+            started_at: activity
+                .timestamps
+                .as_ref()
+                .and_then(|ts| ts.start)
+                .or(activity.created_at),
+        })
+    }
+
+    /// How full the party is, from `0.0` (empty) to `1.0` (full)
+    pub fn fill_ratio(&self) -> f32 {
+        let (current, max) = self.party_size;
+        if max > 0 {
+            current as f32 / max as f32
+        } else {
+            1.0
+        }
+    }
+
+    /// The number of free slots in the party
+    pub fn free_slots(&self) -> u32 {
+        let (current, max) = self.party_size;
+        max.saturating_sub(current)
+    }
+
+    /// How long the friend's session has been going on for
+    pub fn session_age(&self) -> Option<time::Duration> {
+        self.started_at
+            .map(|started_at| time::OffsetDateTime::now_utc() - started_at)
+    }
+
+    /// Joins the friend's game by feeding their join secret into the
+    /// application's regular join path, ie. it is published on the
+    /// [`ActivitySpoke`](crate::wheel::ActivitySpoke) as an
+    /// [`ActivityEvent::Join`], just as if Discord had sent it, so that
+    /// anything waiting for a join, such as a
+    /// [`JoinFlow`](crate::activity::join::JoinFlow), receives it.
+    pub fn join(&self, wheel: &Wheel) {
+        wheel.publish_activity(ActivityEvent::Join(SecretEvent {
+            secret: self.join_secret.clone(),
+        }));
+    }
+}
+
+impl Relationships {
+    /// Gets the friends that are playing the specified application in a party
+    /// that can be joined, ordered with the fullest parties first.
+    ///
+    /// Since this is a query of the current state, it is always up to date as
+    /// long as [`Relationships::on_event`] is called with every
+    /// [`RelationshipEvent`](super::events::RelationshipEvent), or if the
+    /// state is the one maintained by [`Wheel::relationship_state`].
+    pub fn joinable(&self, app_id: crate::AppId) -> Vec<JoinableFriend> {
+        let mut joinable: Vec<_> = self
+            .all()
+            .iter()
+            .filter_map(|rel| JoinableFriend::from_relationship(rel, app_id))
+            .collect();
+
+        joinable.sort_by(|a, b| {
+            b.fill_ratio().total_cmp(&a.fill_ratio()).then_with(|| {
+                a.relationship
+                    .user
+                    .username
+                    .cmp(&b.relationship.user.username)
+            })
+        });
+
+        joinable
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        activity::{Party, Secrets},
+        relations::{
+            events::RelationshipEvent, RelationStatus, RelationshipActivity, RelationshipPresence,
+        },
+        user::test_user,
+    };
+
+    const APP: crate::AppId = 310270644849737729;
+
+    fn friend(
+        id: u64,
+        kind: RelationKind,
+        app: crate::AppId,
+        size: (u32, u32),
+        join: bool,
+    ) -> Relationship {
+        Relationship {
+            kind,
+            user: test_user(id),
+            presence: RelationshipPresence {
+                status: RelationStatus::Online,
+                activity: Some(RelationshipActivity {
+                    application_id: Some(app),
+                    created_at: Some(crate::util::timestamp(1628629162447)),
+                    party: Some(Party {
+                        id: format!("party{id}"),
+                        size: Some(size),
+                        privacy: None,
+                    }),
+                    secrets: join.then(|| Secrets {
+                        join: Some(format!("secret{id}")),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn discovers() {
+        let rels = Relationships::new(vec![
+            friend(1, RelationKind::Friend, APP, (1, 4), true),
+            friend(2, RelationKind::Friend, APP, (3, 4), true),
+            // Full
+            friend(3, RelationKind::Friend, APP, (4, 4), true),
+            // No join secret
+            friend(4, RelationKind::Friend, APP, (1, 4), false),
+            // Different application
+            friend(5, RelationKind::Friend, 1, (1, 4), true),
+            // Not a friend
+            friend(6, RelationKind::Implicit, APP, (1, 4), true),
+        ]);
+
+        let joinable = rels.joinable(APP);
+        let ids: Vec<_> = joinable.iter().map(|jf| jf.party_id.as_str()).collect();
+        assert_eq!(ids, ["party2", "party1"]);
+        assert_eq!(joinable[0].fill_ratio(), 0.75);
+        assert_eq!(joinable[0].free_slots(), 1);
+        assert!(joinable[0].session_age().is_some());
+
+        // The list is kept up to date by relationship events
+        rels.on_event(RelationshipEvent::Update(Arc::new(friend(
+            2,
+            RelationKind::Friend,
+            APP,
+            (4, 4),
+            true,
+        ))));
+        let joinable = rels.joinable(APP);
+        let ids: Vec<_> = joinable.iter().map(|jf| jf.party_id.as_str()).collect();
+        assert_eq!(ids, ["party1"]);
+
+        // Joining feeds the secret to anything listening for joins
+        let (wheel, _handler) = Wheel::new(Box::new(|_err| {}));
+        let mut activity = wheel.activity().0;
+        joinable[0].join(&wheel);
+        assert!(matches!(
+            activity.recv().await.unwrap(),
+            ActivityEvent::Join(secret) if secret.secret == "secret1"
+        ));
+        drop(activity);
+
+        // Or is retained until the application's join flow claims it
+        joinable[0].join(&wheel);
+        let secret: String =
+            crate::activity::join::JoinFlow::wait(&wheel, std::time::Duration::from_millis(10))
+                .await
+                .unwrap();
+        assert_eq!(secret, "secret1");
+    }
+}
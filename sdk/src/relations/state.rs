@@ -1,29 +1,248 @@
-use crate::relations::{events::RelationshipEvent, Relationship};
+use crate::{
+    relations::{events::RelationshipEvent, RelationKind, RelationStatus, Relationship},
+    user::UserId,
+};
 use parking_lot::RwLock;
+use std::{collections::HashMap, sync::Arc};
 
-#[derive(Debug)]
+/// A change to the [`Relationships`] state caused by a [`RelationshipEvent`]
+#[derive(Debug, Clone)]
+pub enum RelationshipDiff {
+    /// A relationship with a user was added
+    Added(Arc<Relationship>),
+    /// A relationship with a user was removed, ie. its kind became
+    /// [`RelationKind::None`]
+    Removed(Arc<Relationship>),
+    /// The kind of relationship with a user changed, eg. a pending friend
+    /// request was accepted
+    KindChanged {
+        old: Arc<Relationship>,
+        new: Arc<Relationship>,
+    },
+    /// The presence of a user changed, ie. their status or activity
+    PresenceChanged {
+        old: Arc<Relationship>,
+        new: Arc<Relationship>,
+    },
+    /// The user's profile changed, ie. their username, display name,
+    /// discriminator, or avatar
+    UserChanged {
+        old: Arc<Relationship>,
+        new: Arc<Relationship>,
+    },
+}
+
+/// The current user's relationships, indexed by the id of the other user
+#[derive(Debug, Default)]
 pub struct Relationships {
-    pub relationships: RwLock<Vec<std::sync::Arc<Relationship>>>,
+    relationships: RwLock<HashMap<UserId, Arc<Relationship>>>,
 }
 
 impl Relationships {
     pub fn new(relations: Vec<Relationship>) -> Self {
         Self {
-            relationships: RwLock::new(relations.into_iter().map(std::sync::Arc::new).collect()),
+            relationships: RwLock::new(
+                relations
+                    .into_iter()
+                    .filter(|rel| rel.kind != RelationKind::None)
+                    .map(|rel| (rel.user.id, Arc::new(rel)))
+                    .collect(),
+            ),
         }
     }
 
-    pub fn on_event(&self, re: RelationshipEvent) {
+    /// Replaces the entire state, eg. with the result of
+    /// [`Discord::get_relationships`](crate::Discord::get_relationships)
+    pub fn replace(&self, relations: Vec<Relationship>) {
+        *self.relationships.write() = Self::new(relations).relationships.into_inner();
+    }
+
+    /// Updates the state with the event, returning the changes it caused
+    pub fn on_event(&self, re: RelationshipEvent) -> Vec<RelationshipDiff> {
         match re {
-            RelationshipEvent::Update(rel) => {
+            RelationshipEvent::Update(new) => {
                 let mut rels = self.relationships.write();
-                match rels.iter().position(|r| r.user.id == rel.user.id) {
-                    Some(i) => {
-                        rels[i] = rel;
+
+                if new.kind == RelationKind::None {
+                    return rels
+                        .remove(&new.user.id)
+                        .map(RelationshipDiff::Removed)
+                        .into_iter()
+                        .collect();
+                }
+
+                match rels.insert(new.user.id, new.clone()) {
+                    Some(old) => {
+                        let mut diffs = Vec::new();
+
+                        if old.kind != new.kind {
+                            diffs.push(RelationshipDiff::KindChanged {
+                                old: old.clone(),
+                                new: new.clone(),
+                            });
+                        }
+
+                        if old.presence != new.presence {
+                            diffs.push(RelationshipDiff::PresenceChanged {
+                                old: old.clone(),
+                                new: new.clone(),
+                            });
+                        }
+
+                        let (ou, nu) = (&old.user, &new.user);
+                        if ou.username != nu.username
+                            || ou.global_name != nu.global_name
+                            || ou.discriminator != nu.discriminator
+                            || ou.avatar != nu.avatar
+                        {
+                            diffs.push(RelationshipDiff::UserChanged { old, new });
+                        }
+
+                        diffs
                     }
-                    None => rels.push(rel),
+                    None => vec![RelationshipDiff::Added(new)],
                 }
             }
         }
     }
+
+    /// Gets the relationship with the specified user
+    pub fn get(&self, id: UserId) -> Option<Arc<Relationship>> {
+        self.relationships.read().get(&id).cloned()
+    }
+
+    /// The number of relationships
+    pub fn len(&self) -> usize {
+        self.relationships.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.relationships.read().is_empty()
+    }
+
+    /// Gets all of the relationships
+    pub fn all(&self) -> Vec<Arc<Relationship>> {
+        self.filter(|_| true)
+    }
+
+    /// Gets all of the relationships that match the predicate, similarly to
+    /// the [filter](https://discord.com/developers/docs/game-sdk/relationships#filter)
+    /// in the regular Game SDK
+    pub fn filter(&self, pred: impl Fn(&Relationship) -> bool) -> Vec<Arc<Relationship>> {
+        self.relationships
+            .read()
+            .values()
+            .filter(|rel| pred(rel))
+            .cloned()
+            .collect()
+    }
+
+    /// Gets all of the users that are friends with the current user
+    pub fn friends(&self) -> Vec<Arc<Relationship>> {
+        self.filter(|rel| rel.kind == RelationKind::Friend)
+    }
+
+    /// Gets all of the friends that are not offline
+    pub fn online(&self) -> Vec<Arc<Relationship>> {
+        self.filter(|rel| {
+            rel.kind == RelationKind::Friend && rel.presence.status != RelationStatus::Offline
+        })
+    }
+
+    /// Gets all of the users with the specified status
+    pub fn by_status(&self, status: RelationStatus) -> Vec<Arc<Relationship>> {
+        self.filter(|rel| rel.presence.status == status)
+    }
+
+    /// Gets all of the users that currently have an activity for the
+    /// specified application
+    pub fn playing(&self, app_id: crate::AppId) -> Vec<Arc<Relationship>> {
+        self.filter(|rel| {
+            rel.presence
+                .activity
+                .as_ref()
+                .is_some_and(|activity| activity.application_id == Some(app_id))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        relations::{RelationshipActivity, RelationshipPresence},
+        types::Snowflake,
+        user::test_user,
+    };
+
+    fn rel(id: u64, kind: RelationKind, status: RelationStatus) -> RelationshipEvent {
+        RelationshipEvent::Update(Arc::new(Relationship {
+            kind,
+            user: test_user(id),
+            presence: RelationshipPresence {
+                status,
+                activity: None,
+            },
+        }))
+    }
+
+    #[test]
+    fn diffs() {
+        let rels = Relationships::default();
+
+        assert!(matches!(
+            rels.on_event(rel(
+                1,
+                RelationKind::PendingIncoming,
+                RelationStatus::Offline
+            ))
+            .as_slice(),
+            [RelationshipDiff::Added(_)]
+        ));
+        assert!(matches!(
+            rels.on_event(rel(1, RelationKind::Friend, RelationStatus::Online))
+                .as_slice(),
+            [
+                RelationshipDiff::KindChanged { .. },
+                RelationshipDiff::PresenceChanged { .. }
+            ]
+        ));
+        assert!(rels
+            .on_event(rel(1, RelationKind::Friend, RelationStatus::Online))
+            .is_empty());
+
+        let RelationshipEvent::Update(mut renamed) =
+            rel(1, RelationKind::Friend, RelationStatus::Online);
+        Arc::get_mut(&mut renamed).unwrap().user.global_name = Some("Renamed".to_owned());
+        assert!(matches!(
+            rels.on_event(RelationshipEvent::Update(renamed)).as_slice(),
+            [RelationshipDiff::UserChanged { .. }]
+        ));
+
+        rels.on_event(rel(2, RelationKind::Friend, RelationStatus::Offline));
+        rels.on_event(rel(3, RelationKind::Blocked, RelationStatus::Online));
+        assert_eq!(rels.len(), 3);
+        assert_eq!(rels.friends().len(), 2);
+        assert_eq!(rels.online().len(), 1);
+        assert_eq!(rels.by_status(RelationStatus::Online).len(), 2);
+
+        assert!(matches!(
+            rels.on_event(rel(3, RelationKind::None, RelationStatus::Online))
+                .as_slice(),
+            [RelationshipDiff::Removed(_)]
+        ));
+        assert!(rels.get(Snowflake(3)).is_none());
+        assert!(rels
+            .on_event(rel(3, RelationKind::None, RelationStatus::Online))
+            .is_empty());
+
+        let RelationshipEvent::Update(mut playing) =
+            rel(2, RelationKind::Friend, RelationStatus::Online);
+        Arc::get_mut(&mut playing).unwrap().presence.activity = Some(RelationshipActivity {
+            application_id: Some(310270644849737729),
+            ..Default::default()
+        });
+        rels.on_event(RelationshipEvent::Update(playing));
+        assert_eq!(rels.playing(310270644849737729).len(), 1);
+    }
 }
@@ -0,0 +1,267 @@
+//! Provides semantic [`PresenceTransition`]s derived from the difference
+//! between a friend's old and new [`RelationshipPresence`]
+
+use super::{
+    RelationKind, RelationStatus, Relationship, RelationshipActivity, RelationshipPresence,
+};
+use std::sync::Arc;
+
+/// A meaningful change in a friend's presence
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PresenceTransition {
+    /// The friend went from offline to any other status
+    CameOnline,
+    /// The friend went from any other status to offline
+    WentOffline,
+    /// The friend started an activity, or switched to an activity for a
+    /// different application
+    StartedPlaying {
+        application_id: Option<crate::AppId>,
+    },
+    /// The friend stopped an activity, or switched to an activity for a
+    /// different application
+    StoppedPlaying {
+        application_id: Option<crate::AppId>,
+    },
+    /// The friend joined a party
+    JoinedParty { party_id: String },
+    /// The friend left a party
+    LeftParty { party_id: String },
+    /// The friend's activity can now be joined, see
+    /// [`RelationshipActivity::is_joinable`]
+    BecameJoinable,
+    /// The friend's activity can no longer be joined
+    NoLongerJoinable,
+}
+
+/// A [`PresenceTransition`] for a specific friend, as broadcast via the
+/// [`PresenceSpoke`](crate::wheel::PresenceSpoke)
+#[derive(Debug, Clone)]
+pub struct PresenceTransitionEvent {
+    /// The friend's relationship, after the transition
+    pub relationship: Arc<Relationship>,
+    pub transition: PresenceTransition,
+}
+
+impl RelationshipActivity {
+    /// Whether the activity can currently be joined, ie. it has a join secret
+    /// and a party with a known size that is not full
+    pub fn is_joinable(&self) -> bool {
+        let has_secret = self
+            .secrets
+            .as_ref()
+            .is_some_and(|secrets| secrets.join.is_some());
+        let has_room = self
+            .party
+            .as_ref()
+            .and_then(|party| party.size)
+            .is_some_and(|(current, max)| current < max);
+
+        has_secret && has_room
+    }
+}
+
+/// Determines the transitions between the old and new presence of a user.
+///
+/// Transitions that end something, eg. [`PresenceTransition::LeftParty`], are
+/// ordered before transitions that start something, with
+/// [`PresenceTransition::CameOnline`] always first and
+/// [`PresenceTransition::WentOffline`] always last.
+pub fn transitions(
+    old: &RelationshipPresence,
+    new: &RelationshipPresence,
+) -> Vec<PresenceTransition> {
+    let mut transitions = Vec::new();
+
+    let was_online = old.status != RelationStatus::Offline;
+    let is_online = new.status != RelationStatus::Offline;
+
+    if !was_online && is_online {
+        transitions.push(PresenceTransition::CameOnline);
+    }
+
+    let old_act = old.activity.as_ref();
+    let new_act = new.activity.as_ref();
+
+    let app = |act: Option<&RelationshipActivity>| act.map(|act| act.application_id);
+    fn party(act: Option<&RelationshipActivity>) -> Option<&String> {
+        act.and_then(|act| act.party.as_ref())
+            .map(|party| &party.id)
+    }
+    let joinable = |act: Option<&RelationshipActivity>| act.is_some_and(|act| act.is_joinable());
+
+    let (old_app, new_app) = (app(old_act), app(new_act));
+    let (old_party, new_party) = (party(old_act), party(new_act));
+    let (was_joinable, is_joinable) = (joinable(old_act), joinable(new_act));
+
+    if was_joinable && !is_joinable {
+        transitions.push(PresenceTransition::NoLongerJoinable);
+    }
+
+    if let Some(party_id) = old_party.filter(|_| old_party != new_party) {
+        transitions.push(PresenceTransition::LeftParty {
+            party_id: party_id.clone(),
+        });
+    }
+
+    if let Some(application_id) = old_app.filter(|_| old_app != new_app) {
+        transitions.push(PresenceTransition::StoppedPlaying { application_id });
+    }
+
+    if let Some(application_id) = new_app.filter(|_| old_app != new_app) {
+        transitions.push(PresenceTransition::StartedPlaying { application_id });
+    }
+
+    if let Some(party_id) = new_party.filter(|_| old_party != new_party) {
+        transitions.push(PresenceTransition::JoinedParty {
+            party_id: party_id.clone(),
+        });
+    }
+
+    if !was_joinable && is_joinable {
+        transitions.push(PresenceTransition::BecameJoinable);
+    }
+
+    if was_online && !is_online {
+        transitions.push(PresenceTransition::WentOffline);
+    }
+
+    transitions
+}
+
+/// Determines the transitions for a change in a relationship, only friends
+/// have transitions. A user that was not a friend before, or was not known at
+/// all, is treated as if they were offline without an activity.
+pub(crate) fn friend_transitions(
+    old: Option<&Relationship>,
+    new: &Arc<Relationship>,
+) -> impl Iterator<Item = PresenceTransitionEvent> {
+    let transitions = if new.kind == RelationKind::Friend {
+        match old.filter(|old| old.kind == RelationKind::Friend) {
+            Some(old) => transitions(&old.presence, &new.presence),
+            None => transitions(
+                &RelationshipPresence {
+                    status: RelationStatus::Offline,
+                    activity: None,
+                },
+                &new.presence,
+            ),
+        }
+    } else {
+        Vec::new()
+    };
+
+    let relationship = new.clone();
+    transitions
+        .into_iter()
+        .map(move |transition| PresenceTransitionEvent {
+            relationship: relationship.clone(),
+            transition,
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::activity::{Party, Secrets};
+
+    fn presence(
+        status: RelationStatus,
+        activity: Option<RelationshipActivity>,
+    ) -> RelationshipPresence {
+        RelationshipPresence { status, activity }
+    }
+
+    fn activity(
+        app: crate::AppId,
+        party: Option<(&str, u32, u32)>,
+        join: bool,
+    ) -> RelationshipActivity {
+        RelationshipActivity {
+            application_id: Some(app),
+            party: party.map(|(id, current, max)| Party {
+                id: id.to_owned(),
+                size: Some((current, max)),
+                privacy: None,
+            }),
+            secrets: join.then(|| Secrets {
+                join: Some("join".to_owned()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn transitions() {
+        use PresenceTransition as PT;
+
+        let offline = presence(RelationStatus::Offline, None);
+        let online = presence(RelationStatus::Online, None);
+        let joinable = presence(
+            RelationStatus::Online,
+            Some(activity(1, Some(("party", 1, 4)), true)),
+        );
+        let full = presence(
+            RelationStatus::Online,
+            Some(activity(1, Some(("party", 4, 4)), true)),
+        );
+        let other_game = presence(RelationStatus::Idle, Some(activity(2, None, false)));
+
+        assert_eq!(super::transitions(&offline, &online), vec![PT::CameOnline]);
+        assert!(super::transitions(&online, &online).is_empty());
+
+        assert_eq!(
+            super::transitions(&online, &joinable),
+            vec![
+                PT::StartedPlaying {
+                    application_id: Some(1)
+                },
+                PT::JoinedParty {
+                    party_id: "party".to_owned()
+                },
+                PT::BecameJoinable,
+            ]
+        );
+        assert_eq!(
+            super::transitions(&joinable, &full),
+            vec![PT::NoLongerJoinable]
+        );
+        assert_eq!(
+            super::transitions(&full, &other_game),
+            vec![
+                PT::LeftParty {
+                    party_id: "party".to_owned()
+                },
+                PT::StoppedPlaying {
+                    application_id: Some(1)
+                },
+                PT::StartedPlaying {
+                    application_id: Some(2)
+                },
+            ]
+        );
+        assert_eq!(
+            super::transitions(&other_game, &offline),
+            vec![
+                PT::StoppedPlaying {
+                    application_id: Some(2)
+                },
+                PT::WentOffline,
+            ]
+        );
+    }
+
+    #[test]
+    fn joinable() {
+        assert!(activity(1, Some(("party", 1, 4)), true).is_joinable());
+        assert!(!activity(1, Some(("party", 4, 4)), true).is_joinable());
+        assert!(!activity(1, Some(("party", 1, 4)), false).is_joinable());
+        assert!(!activity(1, None, true).is_joinable());
+
+        // A party of unknown size can't be joined as it might be full
+        let mut unknown_size = activity(1, Some(("party", 1, 4)), true);
+        unknown_size.party.as_mut().unwrap().size = None;
+        assert!(!unknown_size.is_joinable());
+    }
+}
@@ -0,0 +1,343 @@
+//! Provides [`Stream`]s of events, as an alternative to receiving them via a
+//! [`DiscordHandler`](crate::DiscordHandler) or the [`Wheel`](crate::wheel::Wheel)
+
+use crate::{
+    activity::events::{ActivityEvent, InviteEvent, JoinRequestEvent, SecretEvent},
+    relations::{events::RelationshipEvent, Relationship},
+    user::UserId,
+    Event,
+};
+pub use futures_core::Stream;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::sync::broadcast;
+
+/// The number of events that can be buffered per stream before it lags, note
+/// that broadcast channels round this up to a power of 2
+const CAPACITY: usize = 16;
+
+/// The stream fell behind and the specified number of events were skipped
+/// as a result. The stream continues with the oldest event that is still
+/// available.
+#[derive(thiserror::Error, Debug, Copy, Clone, PartialEq, Eq)]
+#[error("the stream lagged behind and skipped {0} events")]
+pub struct Lagged(pub u64);
+
+type Recv<S> = Pin<
+    Box<
+        dyn Future<
+                Output = (
+                    Result<S, broadcast::error::RecvError>,
+                    broadcast::Receiver<S>,
+                ),
+            > + Send,
+    >,
+>;
+type Filter<T> = Box<dyn Fn(&T) -> bool + Send + Sync>;
+
+async fn recv<S: Clone>(
+    mut rx: broadcast::Receiver<S>,
+) -> (
+    Result<S, broadcast::error::RecvError>,
+    broadcast::Receiver<S>,
+) {
+    (rx.recv().await, rx)
+}
+
+/// A [`Stream`] of events of type `T`, selected from a broadcast of events of
+/// type `S`.
+///
+/// Each item is either an event, or [`Lagged`] if the stream was not polled
+/// often enough to keep up with the events. The stream ends once the
+/// [`Discord`](crate::Discord) it was created from has been
+/// [disconnected](crate::Discord::disconnect) or dropped and its handler task
+/// has exited, and every [`Events`] created from it has been dropped, as each
+/// of them keeps the underlying broadcast open.
+pub struct EventStream<S, T = S> {
+    recv: Option<Recv<S>>,
+    select: fn(S) -> Option<T>,
+    filters: Vec<Filter<T>>,
+}
+
+impl<S, T> EventStream<S, T>
+where
+    S: Clone + Send + 'static,
+{
+    fn new(rx: broadcast::Receiver<S>, select: fn(S) -> Option<T>) -> Self {
+        Self {
+            recv: Some(Box::pin(recv(rx))),
+            select,
+            filters: Vec::new(),
+        }
+    }
+
+    /// Only yields events that match the predicate, in addition to any
+    /// previous filters
+    pub fn filter(mut self, pred: impl Fn(&T) -> bool + Send + Sync + 'static) -> Self {
+        self.filters.push(Box::new(pred));
+        self
+    }
+}
+
+impl<S: Clone + Send + 'static> From<broadcast::Receiver<S>> for EventStream<S> {
+    /// Creates a stream from a broadcast receiver, eg. one of the
+    /// [`Wheel`](crate::wheel::Wheel)'s spokes
+    fn from(rx: broadcast::Receiver<S>) -> Self {
+        Self::new(rx, Some)
+    }
+}
+
+impl<S, T> Stream for EventStream<S, T>
+where
+    S: Clone + Send + 'static,
+{
+    type Item = Result<T, Lagged>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let Some(fut) = self.recv.as_mut() else {
+                return Poll::Ready(None);
+            };
+
+            let (res, rx) = std::task::ready!(fut.as_mut().poll(cx));
+
+            let item = match res {
+                Ok(event) => (self.select)(event).map(Ok),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => Some(Err(Lagged(skipped))),
+                Err(broadcast::error::RecvError::Closed) => {
+                    self.recv = None;
+                    return Poll::Ready(None);
+                }
+            };
+
+            self.recv = Some(Box::pin(recv(rx)));
+
+            match item {
+                Some(Ok(event)) if !self.filters.iter().all(|filter| filter(&event)) => {}
+                Some(item) => return Poll::Ready(Some(item)),
+                None => {}
+            }
+        }
+    }
+}
+
+/// A stream of [`ActivityEvent`]s
+pub type ActivityEvents = EventStream<ActivityEvent>;
+/// A stream of [`ActivityEvent::Join`] secrets
+pub type ActivityJoins = EventStream<ActivityEvent, SecretEvent>;
+/// A stream of [`ActivityEvent::Spectate`] secrets
+pub type ActivitySpectates = EventStream<ActivityEvent, SecretEvent>;
+/// A stream of [`ActivityEvent::JoinRequest`]s
+pub type JoinRequests = EventStream<ActivityEvent, JoinRequestEvent>;
+/// A stream of [`ActivityEvent::Invite`]s
+pub type Invites = EventStream<ActivityEvent, InviteEvent>;
+/// A stream of relationship updates
+pub type RelationshipUpdates = EventStream<RelationshipEvent, Arc<Relationship>>;
+
+impl JoinRequests {
+    /// Only yields join requests from the specified user
+    pub fn filter_user(self, id: UserId) -> Self {
+        self.filter(move |jr| jr.user.id == id)
+    }
+}
+
+impl Invites {
+    /// Only yields invites from the specified user
+    pub fn filter_user(self, id: UserId) -> Self {
+        self.filter(move |invite| invite.0.user.id == id)
+    }
+}
+
+impl RelationshipUpdates {
+    /// Only yields updates to the relationship with the specified user
+    pub fn filter_user(self, id: UserId) -> Self {
+        self.filter(move |rel| rel.user.id == id)
+    }
+}
+
+/// The broadcasts that feed the [`Events`] streams, fed by the handler task
+/// regardless of the [`DiscordHandler`](crate::DiscordHandler) being used
+#[derive(Clone)]
+pub(crate) struct EventBus {
+    activity: broadcast::Sender<ActivityEvent>,
+    relationships: broadcast::Sender<RelationshipEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self {
+            activity: broadcast::channel(CAPACITY).0,
+            relationships: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+impl EventBus {
+    /// Broadcasts the event to any streams that are interested in it
+    pub(crate) fn publish(&self, event: &Event) {
+        // Avoid cloning events no one is listening to
+        if self.activity.receiver_count() > 0 {
+            let ae = match event {
+                Event::ActivityJoin(secret) => Some(ActivityEvent::Join(secret.clone())),
+                Event::ActivitySpectate(secret) => Some(ActivityEvent::Spectate(secret.clone())),
+                Event::ActivityJoinRequest(jr) => Some(ActivityEvent::JoinRequest(jr.clone())),
+                Event::ActivityInvite(invite) => Some(ActivityEvent::Invite(invite.clone())),
+                _ => None,
+            };
+
+            if let Some(ae) = ae {
+                let _ = self.activity.send(ae);
+            }
+        }
+
+        if let Event::RelationshipUpdate(rel) = event {
+            let _ = self
+                .relationships
+                .send(RelationshipEvent::Update(rel.clone()));
+        }
+    }
+}
+
+/// Creates [`Stream`]s of events received from Discord, see [`crate::Discord::events`]
+///
+/// ```no_run
+/// # async fn run(discord: discord_sdk::Discord, friend: discord_sdk::user::UserId) {
+/// use discord_sdk::stream::Stream;
+/// use std::pin::pin;
+///
+/// let mut updates = pin!(discord.events().relationship_updates().filter_user(friend));
+/// while let Some(update) = std::future::poll_fn(|cx| updates.as_mut().poll_next(cx)).await {
+///     match update {
+///         Ok(rel) => println!("{:?}", rel.presence),
+///         Err(lagged) => eprintln!("{lagged}"),
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Events {
+    bus: EventBus,
+}
+
+impl Events {
+    /// All activity events
+    pub fn activity(&self) -> ActivityEvents {
+        EventStream::new(self.bus.activity.subscribe(), Some)
+    }
+
+    /// The secrets of games the current user has joined, see
+    /// [`Event::ActivityJoin`]
+    pub fn activity_joins(&self) -> ActivityJoins {
+        EventStream::new(self.bus.activity.subscribe(), |ae| match ae {
+            ActivityEvent::Join(secret) => Some(secret),
+            _ => None,
+        })
+    }
+
+    /// The secrets of games the current user is spectating, see
+    /// [`Event::ActivitySpectate`]
+    pub fn activity_spectates(&self) -> ActivitySpectates {
+        EventStream::new(self.bus.activity.subscribe(), |ae| match ae {
+            ActivityEvent::Spectate(secret) => Some(secret),
+            _ => None,
+        })
+    }
+
+    /// Requests from other users to join the current user's game, see
+    /// [`Event::ActivityJoinRequest`]
+    pub fn join_requests(&self) -> JoinRequests {
+        EventStream::new(self.bus.activity.subscribe(), |ae| match ae {
+            ActivityEvent::JoinRequest(jr) => Some(jr),
+            _ => None,
+        })
+    }
+
+    /// Invites from other users to join their game, see
+    /// [`Event::ActivityInvite`]
+    pub fn invites(&self) -> Invites {
+        EventStream::new(self.bus.activity.subscribe(), |ae| match ae {
+            ActivityEvent::Invite(invite) => Some(invite),
+            _ => None,
+        })
+    }
+
+    /// Updates to the current user's relationships, see
+    /// [`Event::RelationshipUpdate`]
+    pub fn relationship_updates(&self) -> RelationshipUpdates {
+        EventStream::new(self.bus.relationships.subscribe(), |re| match re {
+            RelationshipEvent::Update(rel) => Some(rel),
+        })
+    }
+}
+
+impl crate::Discord {
+    /// Gets [`Stream`]s of the events received from Discord. These are
+    /// available regardless of the [`DiscordHandler`](crate::DiscordHandler)
+    /// being used, as long as the relevant [`Subscriptions`](crate::Subscriptions)
+    /// were used.
+    ///
+    /// Streams only receive events that arrive after they are created.
+    pub fn events(&self) -> Events {
+        Events {
+            bus: self.state.events.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::Snowflake;
+
+    async fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+        std::future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+    }
+
+    fn join_request(id: u64) -> Event {
+        Event::ActivityJoinRequest(JoinRequestEvent {
+            user: crate::user::test_user(id),
+        })
+    }
+
+    #[tokio::test]
+    async fn streams() {
+        let bus = EventBus::default();
+        let events = Events { bus: bus.clone() };
+
+        let mut joins = events.activity_joins();
+        let mut requests = events.join_requests().filter_user(Snowflake(2));
+
+        bus.publish(&join_request(1));
+        bus.publish(&Event::ActivityJoin(SecretEvent {
+            secret: "secret".to_owned(),
+        }));
+        bus.publish(&join_request(2));
+
+        assert_eq!(next(&mut joins).await.unwrap().unwrap().secret, "secret");
+        assert_eq!(
+            next(&mut requests).await.unwrap().unwrap().user.id,
+            Snowflake(2)
+        );
+
+        // Lagging is reported rather than silently skipped
+        let mut lagging = events.activity_joins();
+        for _ in 0..CAPACITY + 2 {
+            bus.publish(&Event::ActivityJoin(SecretEvent {
+                secret: "secret".to_owned(),
+            }));
+        }
+        assert_eq!(next(&mut lagging).await.unwrap().unwrap_err(), Lagged(2));
+        assert!(next(&mut lagging).await.unwrap().is_ok());
+
+        // Streams end when the bus is dropped
+        drop((events, bus));
+        for _ in 0..CAPACITY - 1 {
+            assert!(next(&mut lagging).await.unwrap().is_ok());
+        }
+        assert!(next(&mut lagging).await.is_none());
+    }
+}
@@ -0,0 +1,342 @@
+//! Provides subscribing to, and unsubscribing from, events on the live
+//! connection to Discord
+
+use crate::{
+    io,
+    proto::{Command, CommandKind, EventKind, Rpc},
+    Error, Subscriptions,
+};
+
+/// The subscription flag for each event that can be subscribed to
+const EVENTS: &[(Subscriptions, EventKind)] = &[
+    (Subscriptions::ACTIVITY_INVITE, EventKind::ActivityInvite),
+    (Subscriptions::ACTIVITY_JOIN, EventKind::ActivityJoin),
+    (
+        Subscriptions::ACTIVITY_JOIN_REQUEST,
+        EventKind::ActivityJoinRequest,
+    ),
+    (
+        Subscriptions::ACTIVITY_SPECTATE,
+        EventKind::ActivitySpectate,
+    ),
+    (
+        Subscriptions::CURRENT_USER_UPDATE,
+        EventKind::CurrentUserUpdate,
+    ),
+    (
+        Subscriptions::RELATIONSHIP_UPDATE,
+        EventKind::RelationshipUpdate,
+    ),
+    (Subscriptions::OVERLAY_UPDATE, EventKind::OverlayUpdate),
+];
+
+impl Subscriptions {
+    /// The subscription for the specified event, or `None` if the event is
+    /// always sent by Discord, eg. [`EventKind::Ready`]
+    pub fn for_event(event: EventKind) -> Option<Self> {
+        EVENTS
+            .iter()
+            .find_map(|(sub, evt)| (*evt == event).then_some(*sub))
+    }
+
+    /// The events Discord needs to be subscribed to for these subscriptions
+    pub fn event_kinds(self) -> impl Iterator<Item = EventKind> {
+        EVENTS
+            .iter()
+            .filter(move |(sub, _)| self.contains(*sub))
+            .map(|(_, evt)| *evt)
+    }
+}
+
+impl From<EventKind> for Subscriptions {
+    /// The subscription for the specified event, which is empty if the event
+    /// can't be subscribed to
+    fn from(event: EventKind) -> Self {
+        Self::for_event(event).unwrap_or_else(Self::empty)
+    }
+}
+
+/// Serializes an un/subscribe RPC for the specified event
+pub(crate) fn serialize_rpc(
+    cmd: CommandKind,
+    evt: EventKind,
+    nonce: usize,
+    pid: u32,
+    buffer: &mut Vec<u8>,
+) -> Result<(), Error> {
+    io::serialize_message(
+        io::OpCode::Frame,
+        &Rpc {
+            cmd,
+            evt: Some(evt),
+            nonce: nonce.to_string(),
+            // Unlike EVERY other event, subscribing to OVERLAY_UPDATE requires
+            // an argument... :facepalm:
+            args: matches!(evt, EventKind::OverlayUpdate)
+                .then(|| crate::overlay::OverlayPidArgs::new(pid)),
+        },
+        buffer,
+    )
+}
+
+/// The status of the subscription to an event on the current connection to
+/// Discord
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SubscriptionStatus {
+    /// The event is not subscribed to, or there is no connection to Discord
+    Inactive,
+    /// The subscription has been sent, but Discord has not responded yet
+    Pending,
+    /// Discord confirmed the subscription
+    Active,
+    /// Discord rejected the subscription, the reason is reported as an
+    /// [`Error::SubscriptionFailed`]
+    Failed,
+}
+
+impl crate::State {
+    /// Sends an un/subscribe RPC for each of the events in the subscriptions,
+    /// marking them as pending
+    pub(crate) fn send_subscriptions(
+        &self,
+        send_queue: &crate::cc::Sender<Option<Vec<u8>>>,
+        cmd: CommandKind,
+        subscriptions: Subscriptions,
+    ) -> Result<Vec<Subscription>, Error> {
+        let pid = self.target_pid.load(std::sync::atomic::Ordering::Relaxed);
+
+        subscriptions
+            .event_kinds()
+            .map(|evt| {
+                let rx = self.send_raw_rpc(send_queue, cmd, |nonce, buffer| {
+                    serialize_rpc(cmd, evt, nonce, pid, buffer)
+                })?;
+
+                if cmd == CommandKind::Subscribe {
+                    self.subscription_status
+                        .lock()
+                        .insert(evt, SubscriptionStatus::Pending);
+                }
+
+                Ok(Subscription {
+                    cmd,
+                    evt,
+                    rx,
+                    status: self.subscription_status.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+type StatusMap = parking_lot::Mutex<std::collections::HashMap<EventKind, SubscriptionStatus>>;
+
+/// An un/subscribe RPC that is awaiting a response from Discord
+pub(crate) struct Subscription {
+    cmd: CommandKind,
+    evt: EventKind,
+    rx: tokio::sync::oneshot::Receiver<Result<Command, Error>>,
+    status: std::sync::Arc<StatusMap>,
+}
+
+impl Subscription {
+    /// Waits for Discord's response, updating the status of the subscription
+    pub(crate) async fn wait(self) -> Result<(), Error> {
+        let res = match self.rx.await {
+            Ok(Ok(Command::Subscribe { evt } | Command::Unsubscribe { evt })) => {
+                debug_assert_eq!(evt, self.evt);
+                Ok(())
+            }
+            Ok(Ok(other)) => unreachable!("response {:?} should be impossible", other),
+            Ok(Err(err)) => Err(err),
+            Err(err) => Err(err.into()),
+        };
+
+        let mut status = self.status.lock();
+        match (self.cmd, res) {
+            (CommandKind::Subscribe, Ok(())) => {
+                tracing::debug!(event = ?self.evt, "subscription succeeded");
+                status.insert(self.evt, SubscriptionStatus::Active);
+                Ok(())
+            }
+            (CommandKind::Subscribe, Err(err)) => {
+                status.insert(self.evt, SubscriptionStatus::Failed);
+                Err(Error::SubscriptionFailed {
+                    event: self.evt,
+                    source: Box::new(err),
+                })
+            }
+            (_, Ok(())) => {
+                tracing::debug!(event = ?self.evt, "unsubscription succeeded");
+                status.remove(&self.evt);
+                Ok(())
+            }
+            (_, Err(err)) => Err(err),
+        }
+    }
+}
+
+impl crate::Discord {
+    /// The events currently subscribed to. This is the set that is subscribed
+    /// to each time a connection to Discord is established.
+    pub fn subscriptions(&self) -> Subscriptions {
+        *self.state.subscriptions.lock()
+    }
+
+    /// The status of the subscription to the specified event on the current
+    /// connection
+    pub fn subscription_status(&self, event: EventKind) -> SubscriptionStatus {
+        self.state
+            .subscription_status
+            .lock()
+            .get(&event)
+            .copied()
+            .unwrap_or(SubscriptionStatus::Inactive)
+    }
+
+    /// The events that Discord has confirmed are subscribed to on the current
+    /// connection
+    pub fn active_subscriptions(&self) -> Vec<EventKind> {
+        self.state
+            .subscription_status
+            .lock()
+            .iter()
+            .filter_map(|(evt, status)| (*status == SubscriptionStatus::Active).then_some(*evt))
+            .collect()
+    }
+
+    /// Subscribes to additional events, returning once Discord has confirmed
+    /// each subscription. The subscriptions are remembered and renewed if the
+    /// connection to Discord is lost and later re-established.
+    ///
+    /// # Errors
+    /// Fails with [`Error::SubscriptionFailed`] if Discord rejected any of the
+    /// subscriptions, or [`Error::NoConnection`] if the handshake with Discord
+    /// has not completed yet, in which case the subscriptions are still made
+    /// once it does
+    pub async fn subscribe(&self, subscriptions: Subscriptions) -> Result<(), Error> {
+        self.state.subscriptions.lock().insert(subscriptions);
+        self.send_subscriptions(CommandKind::Subscribe, subscriptions)
+            .await
+    }
+
+    /// Unsubscribes from events, returning once Discord has confirmed each
+    /// unsubscription. The events are not subscribed to again if the
+    /// connection to Discord is lost and later re-established.
+    ///
+    /// # Errors
+    /// Fails with [`Error::NoConnection`] if the handshake with Discord has
+    /// not completed yet, in which case the events are still not subscribed
+    /// to once it does
+    pub async fn unsubscribe(&self, subscriptions: Subscriptions) -> Result<(), Error> {
+        self.state.subscriptions.lock().remove(subscriptions);
+        self.send_subscriptions(CommandKind::Unsubscribe, subscriptions)
+            .await
+    }
+
+    async fn send_subscriptions(
+        &self,
+        cmd: CommandKind,
+        subscriptions: Subscriptions,
+    ) -> Result<(), Error> {
+        // Anything sent before the handshake is dropped, the handler task
+        // will subscribe to the current set once it completes
+        if self.state.session.borrow().is_none() {
+            return Err(Error::NoConnection);
+        }
+
+        let pending = self
+            .state
+            .send_subscriptions(&self.send_queue, cmd, subscriptions)?;
+
+        // Wait for every response so that the status of each is updated, but
+        // only report the first failure
+        let mut result = Ok(());
+        for sub in pending {
+            if let Err(err) = sub.wait().await {
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn subscriptions() {
+        assert_eq!(
+            (Subscriptions::USER | Subscriptions::OVERLAY)
+                .event_kinds()
+                .collect::<Vec<_>>(),
+            [EventKind::CurrentUserUpdate, EventKind::OverlayUpdate]
+        );
+        assert_eq!(Subscriptions::ALL.event_kinds().count(), 7);
+        assert_eq!(Subscriptions::empty().event_kinds().count(), 0);
+        assert_eq!(
+            Subscriptions::ACTIVITY_JOIN
+                .event_kinds()
+                .collect::<Vec<_>>(),
+            [EventKind::ActivityJoin]
+        );
+
+        // Every event maps back to the flag it came from
+        for evt in Subscriptions::ALL.event_kinds() {
+            assert_eq!(
+                Subscriptions::for_event(evt)
+                    .unwrap()
+                    .event_kinds()
+                    .collect::<Vec<_>>(),
+                [evt]
+            );
+        }
+        assert_eq!(Subscriptions::for_event(EventKind::Ready), None);
+        assert!(Subscriptions::from(EventKind::Error).is_empty());
+
+        let status = std::sync::Arc::new(StatusMap::default());
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let failed = Subscription {
+            cmd: CommandKind::Subscribe,
+            evt: EventKind::ActivityJoin,
+            rx,
+            status: status.clone(),
+        };
+        tx.send(Err(Error::Discord(crate::DiscordErr::Api(
+            crate::DiscordApiErr::Generic {
+                code: Some(4000),
+                message: None,
+            },
+        ))))
+        .unwrap();
+        assert!(matches!(
+            failed.wait().await,
+            Err(Error::SubscriptionFailed {
+                event: EventKind::ActivityJoin,
+                ..
+            })
+        ));
+        assert_eq!(
+            status.lock().get(&EventKind::ActivityJoin),
+            Some(&SubscriptionStatus::Failed)
+        );
+
+        let mut buffer = Vec::new();
+        serialize_rpc(
+            CommandKind::Unsubscribe,
+            EventKind::OverlayUpdate,
+            2,
+            42,
+            &mut buffer,
+        )
+        .unwrap();
+        assert_eq!(
+            std::str::from_utf8(&buffer[8..]).unwrap(),
+            r#"{"cmd":"UNSUBSCRIBE","nonce":"2","evt":"OVERLAY_UPDATE","args":{"pid":42}}"#
+        );
+    }
+}
@@ -10,7 +10,7 @@ pub(crate) struct CloseFrame<'frame> {
     pub(crate) message: Option<&'frame str>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 #[cfg_attr(test, derive(Serialize))]
 #[allow(dead_code)]
 pub struct ErrorPayload {
@@ -53,7 +53,7 @@ pub struct DiscordConfig {
 
 /// Discord uses [snowflakes](https://discord.com/developers/docs/reference#snowflakes)
 /// for most/all of their unique identifiers, including users, lobbies, etc
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Snowflake(pub u64);
 
 impl Snowflake {
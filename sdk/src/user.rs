@@ -9,12 +9,20 @@ use std::{convert::TryFrom, fmt};
 pub type UserId = crate::types::Snowflake;
 
 /// The MD5 hash of a user's avatar
-#[derive(Clone)]
-pub struct Avatar(pub [u8; 16]);
+#[derive(Clone, PartialEq, Eq)]
+pub struct Avatar {
+    pub md5: [u8; 16],
+    /// Whether the avatar is animated, in which case it is also available as
+    /// a GIF
+    pub is_animated: bool,
+}
 
 impl Avatar {
     pub(crate) fn from_str(ava_str: &str) -> Option<Self> {
-        let avatar = ava_str.strip_prefix("a_").unwrap_or(ava_str);
+        let (avatar, is_animated) = match ava_str.strip_prefix("a_") {
+            Some(avatar) => (avatar, true),
+            None => (ava_str, false),
+        };
 
         if avatar.len() != 32 {
             None
@@ -47,8 +55,30 @@ impl Avatar {
                 md5[ind] = cur;
             }
 
-            Some(Self(md5))
+            Some(Self { md5, is_animated })
+        }
+    }
+}
+
+/// Displays the avatar hash exactly as Discord does, ie. hex encoded and
+/// prefixed with `a_` if the avatar is animated
+impl fmt::Display for Avatar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_animated {
+            f.write_str("a_")?;
+        }
+
+        for byte in self.md5 {
+            write!(f, "{:02x}", byte)?;
         }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Avatar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Avatar({})", self)
     }
 }
 
@@ -58,14 +88,91 @@ impl serde::Serialize for Avatar {
     where
         S: serde::ser::Serializer,
     {
-        use std::fmt::Write;
-        let mut hex_str = String::with_capacity(32);
+        serializer.collect_str(self)
+    }
+}
 
-        for byte in self.0 {
-            let _ = write!(&mut hex_str, "{:02x}", byte);
+/// The image formats avatars can be retrieved in
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    /// WebP, which is animated for animated avatars
+    WebP,
+    /// GIF, only available for animated avatars, static avatars are retrieved
+    /// as PNG instead
+    Gif,
+}
+
+impl ImageFormat {
+    #[inline]
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::WebP => "webp",
+            Self::Gif => "gif",
         }
+    }
+}
 
-        serializer.serialize_str(&hex_str)
+bitflags::bitflags! {
+    /// The public flags on a user's account
+    ///
+    /// [API docs](https://discord.com/developers/docs/resources/user#user-object-user-flags)
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+    pub struct UserFlags: u32 {
+        /// Discord employee
+        const STAFF = 1 << 0;
+        /// Partnered server owner
+        const PARTNER = 1 << 1;
+        /// HypeSquad events member
+        const HYPESQUAD = 1 << 2;
+        const BUG_HUNTER_LEVEL_1 = 1 << 3;
+        /// House Bravery member
+        const HYPESQUAD_ONLINE_HOUSE_1 = 1 << 6;
+        /// House Brilliance member
+        const HYPESQUAD_ONLINE_HOUSE_2 = 1 << 7;
+        /// House Balance member
+        const HYPESQUAD_ONLINE_HOUSE_3 = 1 << 8;
+        const PREMIUM_EARLY_SUPPORTER = 1 << 9;
+        /// The user is a team
+        const TEAM_PSEUDO_USER = 1 << 10;
+        const BUG_HUNTER_LEVEL_2 = 1 << 14;
+        const VERIFIED_BOT = 1 << 16;
+        /// Early verified bot developer
+        const VERIFIED_DEVELOPER = 1 << 17;
+        /// Moderator Programs alumni
+        const CERTIFIED_MODERATOR = 1 << 18;
+        /// Bot uses only HTTP interactions
+        const BOT_HTTP_INTERACTIONS = 1 << 19;
+        const ACTIVE_DEVELOPER = 1 << 22;
+    }
+}
+
+/// The type of Nitro subscription a user has
+///
+/// [API docs](https://discord.com/developers/docs/resources/user#user-object-premium-types)
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PremiumType {
+    #[default]
+    None,
+    NitroClassic,
+    Nitro,
+    NitroBasic,
+    /// A subscription type this crate doesn't know about yet
+    Unknown(u8),
+}
+
+impl From<u8> for PremiumType {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::None,
+            1 => Self::NitroClassic,
+            2 => Self::Nitro,
+            3 => Self::NitroBasic,
+            unknown => Self::Unknown(unknown),
+        }
     }
 }
 
@@ -78,13 +185,20 @@ pub struct User {
     pub id: UserId,
     /// The username
     pub username: String,
+    /// The user's display name, if they have set one
+    pub global_name: Option<String>,
     /// The user's unique discriminator (ie. the #<number> after their name) to
-    /// disambiguate between users with the same username
+    /// disambiguate between users with the same username. Users that have
+    /// migrated to the new username system don't have a discriminator.
     pub discriminator: Option<u32>,
     /// The MD5 hash of the user's avatar
     pub avatar: Option<Avatar>,
     /// Whether the user belongs to an `OAuth2` application
     pub is_bot: bool,
+    /// The public flags on the user's account
+    pub flags: UserFlags,
+    /// The type of Nitro subscription the user has
+    pub premium_type: PremiumType,
 }
 
 impl<'de> Deserialize<'de> for User {
@@ -105,29 +219,118 @@ impl serde::Serialize for User {
     {
         use serde::ser::SerializeStruct;
 
-        let mut state = serializer.serialize_struct("User", 5)?;
+        let mut state = serializer.serialize_struct("User", 8)?;
         state.serialize_field("id", &self.id)?;
         state.serialize_field("username", &self.username)?;
+        state.serialize_field("global_name", &self.global_name)?;
         state.serialize_field(
             "discriminator",
             &self.discriminator.unwrap_or_default().to_string(),
         )?;
         state.serialize_field("avatar", &self.avatar)?;
         state.serialize_field("bot", &self.is_bot)?;
+        state.serialize_field("flags", &self.flags.bits())?;
+        state.serialize_field(
+            "premium_type",
+            &match self.premium_type {
+                PremiumType::None => 0,
+                PremiumType::NitroClassic => 1,
+                PremiumType::Nitro => 2,
+                PremiumType::NitroBasic => 3,
+                PremiumType::Unknown(value) => value,
+            },
+        )?;
         state.end()
     }
 }
 
+/// Creates a user with the specified id, and placeholder values for the rest
+#[cfg(test)]
+pub(crate) fn test_user(id: u64) -> User {
+    User {
+        id: crate::types::Snowflake(id),
+        username: format!("user{id}"),
+        global_name: None,
+        discriminator: None,
+        avatar: None,
+        is_bot: false,
+        flags: UserFlags::empty(),
+        premium_type: PremiumType::None,
+    }
+}
+
 impl fmt::Debug for User {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("User")
             .field("id", &self.id)
             .field("username", &self.username)
+            .field("global_name", &self.global_name)
             .field("discriminator", &self.discriminator)
             .finish()
     }
 }
 
+impl User {
+    /// The name Discord shows for the user, ie. their display name if they
+    /// have set one, or else their username
+    pub fn display_name(&self) -> &str {
+        self.global_name.as_deref().unwrap_or(&self.username)
+    }
+
+    /// Gets the url for the user's avatar, with the specified size in pixels,
+    /// which must be a power of 2 between 16 and 4096.
+    ///
+    /// If the user doesn't have an avatar, the url for the default avatar
+    /// Discord shows for them is returned instead, which is always a PNG.
+    ///
+    /// # Errors
+    /// Fails if the size is invalid, or the [CDN host](crate::DiscordConfig::cdn_host)
+    /// is not a valid host
+    pub fn avatar_url(
+        &self,
+        config: &crate::types::DiscordConfig,
+        size: u32,
+        format: ImageFormat,
+    ) -> Result<url::Url, Error> {
+        if !size.is_power_of_two() || !(16..=4096).contains(&size) {
+            return Err(Error::InvalidImageSize(size));
+        }
+
+        let path = match &self.avatar {
+            Some(avatar) => {
+                let format = match format {
+                    ImageFormat::Gif if !avatar.is_animated => ImageFormat::Png,
+                    format => format,
+                };
+
+                format!("avatars/{}/{}.{}", self.id, avatar, format.extension())
+            }
+            None => format!("embed/avatars/{}.png", self.default_avatar_index()),
+        };
+
+        let mut url = url::Url::parse(&format!("https://{}/{}", config.cdn_host, path))
+            .map_err(|_err| Error::InvalidField("cdn_host"))?;
+
+        url.query_pairs_mut().append_pair("size", &size.to_string());
+
+        if format == ImageFormat::WebP && self.avatar.as_ref().is_some_and(|a| a.is_animated) {
+            url.query_pairs_mut().append_pair("animated", "true");
+        }
+
+        Ok(url)
+    }
+
+    /// The index of the default avatar for the user. Users that still have a
+    /// discriminator use the legacy rule, while users that have migrated to
+    /// the new username system have theirs derived from their id.
+    fn default_avatar_index(&self) -> u64 {
+        match self.discriminator {
+            Some(disc) => disc as u64 % 5,
+            None => (self.id.0 >> 22) % 6,
+        }
+    }
+}
+
 /// Display the name of the user exactly as Discord does, eg `john.smith#1337`.
 impl fmt::Display for User {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -141,6 +344,26 @@ impl fmt::Display for User {
     }
 }
 
+impl crate::Discord {
+    /// Retrieves information about the user with the specified id
+    ///
+    /// # Errors
+    /// Fails with [`DiscordApiErr::UnknownUser`](crate::DiscordApiErr::UnknownUser)
+    /// if the user does not exist
+    pub async fn get_user(&self, id: UserId) -> Result<User, Error> {
+        #[derive(serde::Serialize)]
+        struct GetUser {
+            id: UserId,
+        }
+
+        let rx = self.send_rpc(crate::proto::CommandKind::GetUser, GetUser { id })?;
+
+        handle_response!(rx, crate::proto::Command::GetUser(user) => {
+            Ok(user)
+        })
+    }
+}
+
 /// Inner type purely used for deserialization because writing it manually is
 /// annoying.
 ///
@@ -152,6 +375,10 @@ struct DeUser<'u> {
     id: Option<UserId>,
     /// The user's username
     username: Option<&'u str>,
+    /// The user's display name, which, unlike the username, can contain
+    /// characters that need to be escaped
+    #[serde(borrow)]
+    global_name: Option<std::borrow::Cow<'u, str>>,
     /// A u32 discriminator (serialized as a string, again) to disambiguate
     /// between users with the same username
     discriminator: Option<&'u str>,
@@ -159,6 +386,10 @@ struct DeUser<'u> {
     avatar: Option<&'u str>,
     /// Whether the user belongs to an `OAuth2` application
     bot: Option<bool>,
+    /// The public flags on the user's account
+    flags: Option<u32>,
+    /// The type of Nitro subscription the user has
+    premium_type: Option<u8>,
 }
 
 impl<'de> TryFrom<DeUser<'de>> for User {
@@ -178,7 +409,10 @@ impl<'de> TryFrom<DeUser<'de>> for User {
                     .map_err(|_err| Error::InvalidField("discriminator"))?,
             ),
             None => None,
-        };
+        }
+        // Users that have migrated to the new username system have a
+        // discriminator of "0"
+        .filter(|d| *d != 0);
         // We don't really do anything with this so it's allowed to fail
         let avatar = match u.avatar {
             Some(a) => Avatar::from_str(a),
@@ -188,9 +422,135 @@ impl<'de> TryFrom<DeUser<'de>> for User {
         Ok(Self {
             id,
             username,
+            global_name: u.global_name.map(|gn| gn.into_owned()),
             discriminator,
             avatar,
             is_bot: u.bot.unwrap_or(false),
+            flags: UserFlags::from_bits_retain(u.flags.unwrap_or_default()),
+            premium_type: u.premium_type.map(PremiumType::from).unwrap_or_default(),
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{proto::command::CommandFrame, types::ErrorPayloadStack, DiscordApiErr};
+
+    #[test]
+    fn avatar_url() {
+        use super::*;
+
+        let config = crate::types::DiscordConfig {
+            cdn_host: "cdn.discordapp.com".to_owned(),
+            environment: crate::types::Environment::Production,
+            api_endpoint: "//discord.com/api".to_owned(),
+        };
+
+        let mut user = User {
+            id: crate::types::Snowflake(682969165652689005),
+            username: "jake.shadle".to_owned(),
+            global_name: None,
+            discriminator: Some(7557),
+            avatar: Avatar::from_str("a_15bbd75c8ee6610d045852e7ea998a35"),
+            is_bot: false,
+            flags: UserFlags::empty(),
+            premium_type: PremiumType::None,
+        };
+
+        let url = |user: &User, size, format| {
+            user.avatar_url(&config, size, format)
+                .map(|url| url.to_string())
+        };
+
+        assert_eq!(
+            url(&user, 128, ImageFormat::Gif).unwrap(),
+            "https://cdn.discordapp.com/avatars/682969165652689005/a_15bbd75c8ee6610d045852e7ea998a35.gif?size=128"
+        );
+        assert_eq!(
+            url(&user, 64, ImageFormat::WebP).unwrap(),
+            "https://cdn.discordapp.com/avatars/682969165652689005/a_15bbd75c8ee6610d045852e7ea998a35.webp?size=64&animated=true"
+        );
+        assert!(matches!(
+            url(&user, 100, ImageFormat::Png),
+            Err(Error::InvalidImageSize(100))
+        ));
+        assert!(matches!(
+            url(&user, 8192, ImageFormat::Png),
+            Err(Error::InvalidImageSize(8192))
+        ));
+
+        user.avatar = Avatar::from_str("15bbd75c8ee6610d045852e7ea998a35");
+        assert_eq!(
+            url(&user, 16, ImageFormat::Gif).unwrap(),
+            "https://cdn.discordapp.com/avatars/682969165652689005/15bbd75c8ee6610d045852e7ea998a35.png?size=16"
+        );
+
+        // Legacy discriminator rule
+        user.avatar = None;
+        assert_eq!(
+            url(&user, 16, ImageFormat::Jpeg).unwrap(),
+            "https://cdn.discordapp.com/embed/avatars/2.png?size=16"
+        );
+
+        // New username rule
+        user.discriminator = None;
+        assert_eq!(
+            url(&user, 16, ImageFormat::Jpeg).unwrap(),
+            format!(
+                "https://cdn.discordapp.com/embed/avatars/{}.png?size=16",
+                (682969165652689005u64 >> 22) % 6
+            )
+        );
+    }
+
+    #[test]
+    fn get_user() {
+        let response = r#"{"cmd":"GET_USER","data":{"id":"682969165652689005","username":"jake.shadle","discriminator":"7557","avatar":"15bbd75c8ee6610d045852e7ea998a35","bot":false},"evt":null,"nonce":"1"}"#;
+
+        let frame: CommandFrame = serde_json::from_str(response).expect("failed to deserialize");
+        match frame.inner {
+            crate::proto::Command::GetUser(user) => {
+                assert_eq!(user.id.0, 682969165652689005);
+                assert_eq!(user.to_string(), "jake.shadle#7557");
+            }
+            other => panic!("unexpected response {:?}", other),
+        }
+
+        let error = r#"{"code":4000,"message":"Unknown User"}"#;
+        let payload: ErrorPayloadStack<'_> = serde_json::from_str(error).unwrap();
+        assert!(matches!(
+            DiscordApiErr::from(Some(payload)),
+            DiscordApiErr::UnknownUser
+        ));
+    }
+
+    #[test]
+    fn modern_user() {
+        use super::*;
+
+        let user: User = serde_json::from_str(r#"{"id":"682969165652689005","username":"jake.shadle","global_name":"Jake \"Shadle\"","discriminator":"0","avatar":null,"bot":false,"flags":4194560,"premium_type":2}"#).unwrap();
+
+        assert_eq!(user.discriminator, None);
+        assert_eq!(user.to_string(), "jake.shadle");
+        assert_eq!(user.display_name(), "Jake \"Shadle\"");
+        assert_eq!(
+            user.flags,
+            UserFlags::ACTIVE_DEVELOPER | UserFlags::HYPESQUAD_ONLINE_HOUSE_3
+        );
+        assert_eq!(user.premium_type, PremiumType::Nitro);
+
+        let legacy: User = serde_json::from_str(
+            r#"{"id":"682969165652689005","username":"jake.shadle","discriminator":"7557"}"#,
+        )
+        .unwrap();
+        assert_eq!(legacy.display_name(), "jake.shadle");
+        assert_eq!(legacy.to_string(), "jake.shadle#7557");
+        assert_eq!(legacy.premium_type, PremiumType::None);
+
+        let future: User = serde_json::from_str(
+            r#"{"id":"682969165652689005","username":"jake.shadle","premium_type":42}"#,
+        )
+        .unwrap();
+        assert_eq!(future.premium_type, PremiumType::Unknown(42));
+    }
+}
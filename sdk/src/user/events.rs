@@ -1,6 +1,6 @@
 use super::*;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 #[cfg_attr(test, derive(serde::Serialize))]
 pub struct ConnectEvent {
     /// The protocol version, we only support v1, which is fine since that is
@@ -12,7 +12,7 @@ pub struct ConnectEvent {
     pub user: User,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 #[cfg_attr(test, derive(serde::Serialize))]
 pub struct UpdateEvent {
     /// The user that is logged into the Discord application we connected to
@@ -25,9 +25,19 @@ pub struct DisconnectEvent {
     pub reason: crate::Error,
 }
 
+/// The user logged in to Discord changed between connections
+#[derive(Debug)]
+pub struct AccountChangedEvent {
+    /// The user that was previously logged in
+    pub old: User,
+    /// The user that is now logged in
+    pub new: User,
+}
+
 #[derive(Debug)]
 pub enum UserEvent {
     Connect(ConnectEvent),
     Disconnect(DisconnectEvent),
     Update(UpdateEvent),
+    AccountChanged(AccountChangedEvent),
 }
@@ -24,24 +24,14 @@ pub struct Client {
 }
 
 pub async fn make_client(subs: ds::Subscriptions) -> Result<Client, ds::Error> {
-    let (forwarder, mut events) = ds::handlers::Forwarder::new();
+    let (forwarder, events) = ds::handlers::Forwarder::new();
 
     let discord = ds::Discord::new(ds::DiscordApp::PlainId(APP_ID), subs, Box::new(forwarder))?;
 
     tracing::info!("waiting for handshake...");
-    let user = tokio::time::timeout(std::time::Duration::from_secs(5), async {
-        loop {
-            match events.recv().await {
-                Some(msg) => {
-                    if let Msg::Event(ds::Event::Ready(ready)) = msg {
-                        break ready.user;
-                    }
-                }
-                None => panic!("discord closed"),
-            }
-        }
-    })
-    .await?;
+    let user = discord
+        .wait_ready(std::time::Duration::from_secs(5))
+        .await?;
 
     Ok(Client {
         discord,